@@ -2,16 +2,145 @@
 
 //! A helper object for maintaining a map of token id -> minimum fee.
 
-use alloc::{collections::BTreeMap, format, string::String};
-use core::{convert::TryFrom, iter::FromIterator};
+use alloc::{
+    collections::{btree_map, BTreeMap, BTreeSet},
+    format,
+    string::String,
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::{convert::TryFrom, fmt, iter::FromIterator, mem::size_of, ops::Deref, str::FromStr};
 use displaydoc::Display;
 use mc_common::ResponderId;
 use mc_crypto_digestible::{DigestTranscript, Digestible, MerlinTranscript};
-use mc_transaction_core::{tokens::Mob, Token, TokenId};
-use serde::{Deserialize, Serialize};
+use mc_transaction_core::{
+    constants::{MICROMOB_TO_PICOMOB, MILLIMOB_TO_PICOMOB},
+    tokens::Mob,
+    BlockIndex, Token, TokenId, TokenRegistry,
+};
+#[cfg(any(test, feature = "test_utils"))]
+use rand_core::RngCore;
+use serde::{
+    de::{self, DeserializeSeed, Deserializer, MapAccess, Visitor},
+    ser::{SerializeStruct, Serializer},
+    Deserialize, Serialize,
+};
+
+/// The default ceiling for the MOB fee, in picoMOB (1000 MOB). Fees larger
+/// than this would effectively halt all MOB transactions. This is
+/// [max_fee_for_token] for [Mob].
+pub const DEFAULT_MAXIMUM_FEE: u64 = 1_000_000 * MILLIMOB_TO_PICOMOB;
+
+/// The default ceiling for a fee, in whole tokens, for tokens known at compile
+/// time.
+const DEFAULT_MAXIMUM_FEE_WHOLE_TOKENS: u64 = 1_000;
+
+/// The lowest MOB fee accepted by [FeeMap::is_valid_map_with_floors] unless
+/// another floor is configured for MOB (1 microMOB when denominated in
+/// picoMOB).
+pub const DEFAULT_MOB_FEE_FLOOR: u64 = MICROMOB_TO_PICOMOB;
+
+/// The number of basis points in 100%.
+pub const MAX_BASIS_POINTS: u16 = 10_000;
+
+/// The surcharge applied by [estimate_fee] for each input beyond the first, in
+/// basis points of the minimum fee.
+pub const PER_INPUT_SURCHARGE_BASIS_POINTS: u16 = 1_000;
+
+/// The surcharge applied by [estimate_fee] for each output beyond the first, in
+/// basis points of the minimum fee.
+pub const PER_OUTPUT_SURCHARGE_BASIS_POINTS: u16 = 500;
+
+/// A fee, denominated in the smallest unit of its token (e.g. picoMOB for
+/// MOB).
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+#[serde(transparent)]
+pub struct Fee(u64);
+
+impl From<u64> for Fee {
+    fn from(src: u64) -> Self {
+        Self(src)
+    }
+}
+
+impl From<Fee> for u64 {
+    fn from(src: Fee) -> Self {
+        src.0
+    }
+}
+
+impl Deref for Fee {
+    type Target = u64;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl fmt::Display for Fee {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Fee {
+    /// Add two fees, returning None on overflow.
+    pub fn checked_add(self, other: Fee) -> Option<Fee> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    /// Subtract a fee, returning None on underflow.
+    pub fn checked_sub(self, other: Fee) -> Option<Fee> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// Multiply a fee by a count, returning None on overflow.
+    pub fn checked_mul(self, count: u64) -> Option<Fee> {
+        self.0.checked_mul(count).map(Self)
+    }
+
+    /// Add two fees, saturating at `u64::MAX`.
+    pub fn saturating_add(self, other: Fee) -> Fee {
+        Self(self.0.saturating_add(other.0))
+    }
+}
+
+/// How the fee for a token is computed.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum FeePolicy {
+    /// A flat fee, regardless of the amount transacted. This is the default.
+    Flat(u64),
+
+    /// A fee proportional to the amount transacted, in basis points (1/100th
+    /// of a percent), rounded up. The token's minimum fee still applies.
+    ///
+    /// The policy kind and basis points are part of the fee map digest, so
+    /// nodes configured with different policies have distinct responder ids.
+    BasisPoints(u16),
+}
+
+/// Whether a fee map has a fee for a token, as returned by
+/// [FeeMap::fee_status].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FeeStatus {
+    /// The token has a minimum fee, which is always greater than 0.
+    Configured(Fee),
+
+    /// The token has no fee, so transactions in it are not accepted.
+    Unsupported,
+}
 
 /// A thread-safe object that contains a map of fee value by token id.
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+///
+/// This serializes as a map of token id to fee; the digest is recomputed on
+/// deserialization.
+///
+/// Equality and ordering ignore [FeeMap::last_updated_by], since two nodes
+/// with the same fees agree regardless of which peer proposed them.
+#[derive(Debug, Clone)]
 pub struct FeeMap {
     /// The actual map of token_id to fee.
     /// Since we hash this map, it is important to use a BTreeMap as it
@@ -19,17 +148,86 @@ pub struct FeeMap {
     /// order.
     map: BTreeMap<TokenId, u64>,
 
+    /// Proportional fees, in basis points, for tokens that do not use the
+    /// default flat fee. Every token in here also has a minimum fee in `map`.
+    /// These are digested along with the fees; see [FeePolicy::BasisPoints].
+    basis_points: BTreeMap<TokenId, u16>,
+
     /// Cached digest value, formatted as a string.
     /// (Suitable for appending to responder id)
     cached_digest: String,
+
+    /// Cached digest value, as raw bytes.
+    cached_digest_bytes: [u8; 32],
+
+    /// The digest scheme used for the cached digest.
+    digest_version: DigestVersion,
+
+    /// A custom digester used for the cached digest instead of the Merlin
+    /// digest of `digest_version`, if any. See [FeeMap::with_digester].
+    digester: Option<SharedDigester>,
+
+    /// The peer that proposed the most recent update applied with
+    /// [FeeMap::update_from_peer], if any. This is for debugging only, and is
+    /// not digested, serialized or compared.
+    last_updated_by: Option<ResponderId>,
 }
 
+impl PartialEq for FeeMap {
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+            && self.basis_points == other.basis_points
+            && self.cached_digest == other.cached_digest
+            && self.cached_digest_bytes == other.cached_digest_bytes
+            && self.digest_version == other.digest_version
+    }
+}
+
+impl Eq for FeeMap {}
+
 impl Default for FeeMap {
     fn default() -> Self {
-        let map = Self::default_map();
-        let cached_digest = calc_digest_for_map(&map);
+        Self::from_valid_map(Self::default_map()).expect("the default fee map can be digested")
+    }
+}
+
+impl fmt::Display for FeeMap {
+    /// Displays the fees as comma-separated `token_id=fee` pairs, sorted by
+    /// token id, followed by the basis points of any proportional fee, e.g.
+    /// `MOB=400000000, 2=2000 (25 bps)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (token_id, fee)) in self.map.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", token_id, fee)?;
+            if let Some(basis_points) = self.basis_points.get(token_id) {
+                write!(f, " ({} bps)", basis_points)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Fee maps are ordered by their digest, which is canonical, rather than by
+/// comparing fees. The ordering is therefore arbitrary but stable, and is only
+/// meant for e.g. storing fee maps in a `BTreeSet`. Fee maps with the same
+/// digest are ordered by the remaining fields compared by `Eq`, to agree with
+/// it.
+impl Ord for FeeMap {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        self.cached_digest_bytes
+            .cmp(&other.cached_digest_bytes)
+            .then_with(|| self.basis_points.cmp(&other.basis_points))
+            .then_with(|| self.map.cmp(&other.map))
+            .then_with(|| self.digest_version.cmp(&other.digest_version))
+            .then_with(|| self.cached_digest.cmp(&other.cached_digest))
+    }
+}
 
-        Self { map, cached_digest }
+impl PartialOrd for FeeMap {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
     }
 }
 
@@ -39,168 +237,5143 @@ impl TryFrom<BTreeMap<TokenId, u64>> for FeeMap {
     fn try_from(map: BTreeMap<TokenId, u64>) -> Result<Self, Self::Error> {
         Self::is_valid_map(&map)?;
 
-        let cached_digest = calc_digest_for_map(&map);
+        Self::from_valid_map(map)
+    }
+}
+
+impl<'a> IntoIterator for &'a FeeMap {
+    type Item = (&'a TokenId, &'a u64);
+    type IntoIter = btree_map::Iter<'a, TokenId, u64>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.iter()
+    }
+}
+
+impl Serialize for FeeMap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.map.iter().map(|(token_id, fee)| {
+            let entry = SerializedFeeEntry {
+                minimum_fee: *fee,
+                basis_points: self.basis_points.get(token_id).cloned(),
+            };
+            (token_id, entry)
+        }))
+    }
+}
+
+impl<'de> Deserialize<'de> for FeeMap {
+    /// Deserializes a map of token ids to fees. The legacy struct-shaped
+    /// encoding, which held the fees under `map` alongside a `cached_digest`,
+    /// is also accepted. The digest is always recomputed rather than trusted.
+    ///
+    /// Keys that name the same token, such as `"0"` and `"MOB"`, are rejected
+    /// as duplicates, including between the legacy `map` and the top level.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        fn insert_entry<E: de::Error>(
+            entries: &mut BTreeMap<TokenId, SerializedFeeEntry>,
+            token_id: TokenId,
+            entry: SerializedFeeEntry,
+        ) -> Result<(), E> {
+            if entries.insert(token_id, entry).is_some() {
+                return Err(E::custom(Error::DuplicateToken(token_id)));
+            }
+            Ok(())
+        }
+
+        /// Reads the fees under the legacy `map` key into the entries read so
+        /// far.
+        struct LegacyMapSeed<'a>(&'a mut BTreeMap<TokenId, SerializedFeeEntry>);
+
+        impl<'de, 'a> DeserializeSeed<'de> for LegacyMapSeed<'a> {
+            type Value = ();
+
+            fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+                deserializer.deserialize_map(self)
+            }
+        }
+
+        impl<'de, 'a> Visitor<'de> for LegacyMapSeed<'a> {
+            type Value = ();
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of token ids to fees")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<(), A::Error> {
+                while let Some((token_id, minimum_fee)) = access.next_entry::<TokenId, u64>()? {
+                    let entry = SerializedFeeEntry {
+                        minimum_fee,
+                        basis_points: None,
+                    };
+                    insert_entry(self.0, token_id, entry)?;
+                }
+                Ok(())
+            }
+        }
+
+        struct FeeMapVisitor;
+
+        impl<'de> Visitor<'de> for FeeMapVisitor {
+            type Value = BTreeMap<TokenId, SerializedFeeEntry>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of token ids to fees")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                let mut entries = BTreeMap::new();
+                while let Some(key) = access.next_key()? {
+                    match key {
+                        SerializedFeeMapKey::Token(token_id) => {
+                            insert_entry(&mut entries, token_id, access.next_value()?)?;
+                        }
+                        SerializedFeeMapKey::LegacyMap => {
+                            access.next_value_seed(LegacyMapSeed(&mut entries))?;
+                        }
+                        SerializedFeeMapKey::LegacyDigest => {
+                            access.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+                Ok(entries)
+            }
+        }
+
+        let entries = deserializer.deserialize_map(FeeMapVisitor)?;
 
-        Ok(Self { map, cached_digest })
+        // The fee ceiling is a configuration-time check, so we don't apply it
+        // here: a fee map built with a larger ceiling must still round-trip.
+        let map = entries
+            .iter()
+            .map(|(token_id, entry)| (*token_id, entry.minimum_fee))
+            .collect();
+        let mut fee_map = Self::with_max_fee(map, u64::MAX).map_err(de::Error::custom)?;
+
+        for (token_id, entry) in entries {
+            if let Some(basis_points) = entry.basis_points {
+                fee_map
+                    .set_fee_policy(token_id, FeePolicy::BasisPoints(basis_points))
+                    .map_err(de::Error::custom)?;
+            }
+        }
+
+        Ok(fee_map)
+    }
+}
+
+impl Digestible for FeeMap {
+    /// Appends the token id and fee of each entry, in sorted order. The cached
+    /// digest is not included, since it is derived from the map.
+    ///
+    /// This lets a fee map be embedded in a larger transcript, such as a block
+    /// header digest, under a label chosen by the caller. The layout matches
+    /// the fee map's own digest: appending with the context `b"fee_map"` to a
+    /// fresh [MerlinTranscript] created with the label of
+    /// [FeeMap::digest_version] reproduces [FeeMap::digest_bytes], unless the
+    /// fee map uses a custom digester.
+    fn append_to_transcript<DT: DigestTranscript>(
+        &self,
+        context: &'static [u8],
+        transcript: &mut DT,
+    ) {
+        append_fees_with_version(
+            &self.map,
+            &self.basis_points,
+            self.digest_version,
+            context,
+            transcript,
+        );
     }
 }
 
 impl FeeMap {
-    /// Create a fee map from an unsorted iterator.
-    pub fn try_from_iter(iter: impl IntoIterator<Item = (TokenId, u64)>) -> Result<Self, Error> {
-        let map = BTreeMap::from_iter(iter);
-        Self::try_from(map)
+    /// The digest scheme used for the cached digest and responder ids of new
+    /// fee maps. This stays at [DigestVersion::V1] until the network migrates
+    /// in a coordinated upgrade; later versions can be selected with
+    /// [FeeMap::recompute_digest].
+    pub const DIGEST_VERSION: DigestVersion = DigestVersion::V1;
+
+    /// Create a fee map, rejecting any fee larger than `max_fee` instead of
+    /// the per-token [FeeMap::default_max_fee].
+    pub fn with_max_fee(map: BTreeMap<TokenId, u64>, max_fee: u64) -> Result<Self, Error> {
+        Self::is_valid_map_with_max_fee(&map, max_fee)?;
+
+        Self::from_valid_map(map)
     }
 
-    /// Append the fee map digest to an existing responder id, producing a
-    /// responder id that is unique to the current fee configuration.
-    pub fn responder_id(&self, responder_id: &ResponderId) -> ResponderId {
-        ResponderId(format!("{}-{}", responder_id.0, self.cached_digest))
+    /// Create a fee map from a map which the caller has already validated,
+    /// e.g. with [FeeMap::is_valid_map], skipping validation. The digest is
+    /// still computed. This is intended for bulk loaders of large maps.
+    ///
+    /// Passing a map which is not valid (e.g. one with no MOB fee, or with a
+    /// zero fee) produces a fee map which breaks the invariants relied on by
+    /// the rest of this API; only debug builds check for this.
+    pub fn try_from_validated(map: BTreeMap<TokenId, u64>) -> Self {
+        debug_assert!(
+            Self::is_valid_map_with_max_fee(&map, u64::MAX).is_ok(),
+            "fee map passed to try_from_validated is not valid"
+        );
+
+        Self::from_valid_map(map).expect("a validated fee map can be digested")
     }
 
-    /// Get the fee for a given token id, or None if no fee is set for that
-    /// token.
-    pub fn get_fee_for_token(&self, token_id: &TokenId) -> Option<u64> {
-        self.map.get(token_id).cloned()
+    /// Create a fee map whose cached digest, and therefore its
+    /// [FeeMap::digest] and [FeeMap::responder_id], is computed with
+    /// `digester` rather than Merlin, e.g. for attestation by a system which
+    /// does not use Merlin. Later updates keep using `digester`, until
+    /// [FeeMap::recompute_digest] switches back to a Merlin digest.
+    pub fn with_digester<D: FeeMapDigester + Send + Sync + 'static>(
+        map: BTreeMap<TokenId, u64>,
+        digester: D,
+    ) -> Result<Self, Error> {
+        Self::is_valid_map(&map)?;
+
+        let mut fee_map = Self::from_valid_map(BTreeMap::new())?;
+        fee_map.digester = Some(SharedDigester(Arc::new(digester)));
+        fee_map.set_contents(map, BTreeMap::new())?;
+        Ok(fee_map)
     }
 
-    /// Update the fee map with a new one if provided, or reset it to the
-    /// default.
-    pub fn update_or_default(
+    /// Construct a fee map from a map that has already been validated.
+    fn from_valid_map(map: BTreeMap<TokenId, u64>) -> Result<Self, Error> {
+        let mut fee_map = Self {
+            map: BTreeMap::new(),
+            basis_points: BTreeMap::new(),
+            cached_digest: String::new(),
+            cached_digest_bytes: [0u8; 32],
+            digest_version: Self::DIGEST_VERSION,
+            digester: None,
+            last_updated_by: None,
+        };
+        fee_map.set_contents(map, BTreeMap::new())?;
+        Ok(fee_map)
+    }
+
+    /// Replace the fees and recompute the cached digest. This is the only way
+    /// the fees should change, so that the digest is never stale. If the
+    /// digest cannot be computed, the fee map is left unchanged.
+    fn set_contents(
         &mut self,
-        minimum_fees: Option<BTreeMap<TokenId, u64>>,
+        map: BTreeMap<TokenId, u64>,
+        basis_points: BTreeMap<TokenId, u16>,
     ) -> Result<(), Error> {
-        if let Some(minimum_fees) = minimum_fees {
-            Self::is_valid_map(&minimum_fees)?;
+        let digest_bytes = self.calc_own_digest_bytes(&map, &basis_points)?;
 
-            self.map = minimum_fees;
-        } else {
-            self.map = Self::default_map();
+        self.map = map;
+        self.basis_points = basis_points;
+        self.cached_digest_bytes = digest_bytes;
+        self.cached_digest = hex::encode(digest_bytes);
+        Ok(())
+    }
+
+    /// Compute the digest of `map` and `basis_points` with this fee map's
+    /// digest scheme: its custom digester if it has one, which only sees the
+    /// minimum fees, and the Merlin digest of its [DigestVersion] otherwise.
+    fn calc_own_digest_bytes(
+        &self,
+        map: &BTreeMap<TokenId, u64>,
+        basis_points: &BTreeMap<TokenId, u16>,
+    ) -> Result<[u8; 32], Error> {
+        match &self.digester {
+            Some(digester) => Ok(digester.0.digest(map)),
+            None => calc_digest_bytes(map, basis_points, self.digest_version),
+        }
+    }
+
+    /// Create a fee map from an unsorted iterator.
+    pub fn try_from_iter(iter: impl IntoIterator<Item = (TokenId, u64)>) -> Result<Self, Error> {
+        let map = BTreeMap::from_iter(iter);
+        Self::try_from(map)
+    }
+
+    /// Create a fee map from an unsorted iterator, rejecting any token id that
+    /// appears more than once.
+    pub fn try_from_iter_strict(
+        iter: impl IntoIterator<Item = (TokenId, u64)>,
+    ) -> Result<Self, Error> {
+        let mut map = BTreeMap::new();
+        for (token_id, fee) in iter {
+            if map.insert(token_id, fee).is_some() {
+                return Err(Error::DuplicateToken(token_id));
+            }
         }
 
-        // Digest must be updated when the map is updated.
-        self.cached_digest = calc_digest_for_map(&self.map);
+        Self::try_from(map)
+    }
 
-        Ok(())
+    /// Create a default fee map, but with `mob_fee` as the MOB fee instead of
+    /// [Mob::MINIMUM_FEE]. This is intended for test networks.
+    pub fn new_with_mob_fee(mob_fee: u64) -> Result<Self, Error> {
+        Self::try_from_iter([(Mob::ID, mob_fee)])
     }
 
-    /// Check if a given fee map is valid.
-    pub fn is_valid_map(minimum_fees: &BTreeMap<TokenId, u64>) -> Result<(), Error> {
-        // All fees must be greater than 0.
-        if let Some((token_id, fee)) = minimum_fees.iter().find(|(_token_id, fee)| **fee == 0) {
-            return Err(Error::InvalidFee(*token_id, *fee));
+    /// Create a fee map containing the default MOB fee plus the given token
+    /// fees. An entry for MOB overrides the default.
+    pub fn default_for_tokens(tokens: &[(TokenId, u64)]) -> Result<Self, Error> {
+        Self::default().merge(&BTreeMap::from_iter(tokens.iter().cloned()))
+    }
+
+    /// Create a default fee map seeded with a deployment's fixed set of
+    /// tokens. MOB is always included, at [Mob::MINIMUM_FEE] unless the seed
+    /// gives it a fee. Unlike [FeeMap::default_for_tokens], a token id that
+    /// appears more than once in the seed is rejected.
+    pub fn default_with_seed(seed: &[(TokenId, u64)]) -> Result<Self, Error> {
+        let mut map = Self::default_map();
+        let mut seeded = BTreeSet::new();
+        for &(token_id, fee) in seed {
+            if !seeded.insert(token_id) {
+                return Err(Error::DuplicateToken(token_id));
+            }
+            map.insert(token_id, fee);
         }
 
-        // Must have a minimum fee for MOB.
-        if !minimum_fees.contains_key(&Mob::ID) {
-            return Err(Error::MissingFee(Mob::ID));
+        Self::try_from(map)
+    }
+
+    /// Create a random valid fee map for tests, containing MOB and
+    /// `num_tokens` other tokens, each with a fee between 1 and
+    /// [DEFAULT_MAXIMUM_FEE].
+    #[cfg(any(test, feature = "test_utils"))]
+    pub fn sample(num_tokens: usize, rng: &mut impl RngCore) -> FeeMap {
+        let mut map = BTreeMap::new();
+        let mut token_id = Mob::ID;
+        while map.len() < num_tokens + 1 {
+            let fee = 1 + rng.next_u64() % DEFAULT_MAXIMUM_FEE;
+            map.entry(token_id).or_insert(fee);
+            token_id = TokenId::from(rng.next_u32());
         }
 
-        // All good.
-        Ok(())
+        Self::try_from(map).expect("sampled fee maps are valid")
     }
 
-    /// Iterate over all entries in the fee map.
-    pub fn iter(&self) -> impl Iterator<Item = (&TokenId, &u64)> {
-        self.map.iter()
+    /// Create a fee map from a JSON object mapping token ids to fees, e.g.
+    /// `{"0": 400000000, "1": 1024}`. A proportional fee is given as an object
+    /// with its minimum fee and basis points, e.g.
+    /// `{"1": {"minimum_fee": 1024, "basis_points": 25}}`.
+    #[cfg(feature = "std")]
+    pub fn from_json(src: &str) -> Result<Self, Error> {
+        let entries: BTreeMap<String, SerializedFeeEntry> =
+            serde_json::from_str(src).map_err(|err| Error::Parse(format!("{}", err)))?;
+
+        Self::from_string_key_entries(entries)
     }
 
-    /// Helper method for constructing the default fee map.
-    pub fn default_map() -> BTreeMap<TokenId, u64> {
-        let mut map = BTreeMap::new();
-        map.insert(Mob::ID, Mob::MINIMUM_FEE);
-        map
+    /// Create a fee map from a TOML document with a `[fees]` table mapping
+    /// token ids to fees, and an optional `[basis_points]` table mapping token
+    /// ids to proportional fees, e.g.
+    ///
+    /// ```toml
+    /// [fees]
+    /// 0 = 400000000
+    /// 1 = 1024
+    ///
+    /// [basis_points]
+    /// 1 = 25
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn from_toml(src: &str) -> Result<Self, Error> {
+        let doc: FeeMapToml =
+            toml::from_str(src).map_err(|err| Error::Parse(format!("{}", err)))?;
+
+        Self::from_string_keys(doc.fees, doc.basis_points)
     }
-}
 
-fn calc_digest_for_map(map: &BTreeMap<TokenId, u64>) -> String {
-    let mut transcript = MerlinTranscript::new(b"fee_map");
-    transcript.append_seq_header(b"fee_map", map.len() * 2);
-    for (token_id, fee) in map {
-        token_id.append_to_transcript(b"token_id", &mut transcript);
-        fee.append_to_transcript(b"fee", &mut transcript);
+    /// Format the fee map as a TOML document suitable for
+    /// [FeeMap::from_toml]. This fails if a fee is too large to be
+    /// represented as a TOML integer.
+    #[cfg(feature = "std")]
+    pub fn to_toml(&self) -> Result<String, Error> {
+        let doc = FeeMapToml {
+            fees: self
+                .map
+                .iter()
+                .map(|(token_id, fee)| (format!("{}", **token_id), *fee))
+                .collect(),
+            basis_points: self
+                .basis_points
+                .iter()
+                .map(|(token_id, basis_points)| (format!("{}", **token_id), *basis_points))
+                .collect(),
+        };
+
+        toml::to_string(&doc).map_err(|err| Error::Serialize(format!("{}", err)))
     }
 
-    let mut result = [0u8; 32];
-    transcript.extract_digest(&mut result);
-    hex::encode(result)
-}
+    /// Create a fee map from a JSON object mapping token ids to fees, like
+    /// [FeeMap::from_json], without requiring `serde_json`. Since
+    /// [FeeMap::to_json] does not require std either, this allows fee maps to
+    /// be round-tripped through JSON in the enclave.
+    #[cfg(feature = "minijson")]
+    pub fn from_minijson(src: &str) -> Result<Self, Error> {
+        let entries = crate::minijson::parse_fee_object(src).map_err(Error::Parse)?;
 
-/// Fee Map error type.
-#[derive(Clone, Debug, Deserialize, Display, PartialEq, PartialOrd, Serialize)]
-pub enum Error {
-    /// Token `{0}` has invalid fee `{1}`
-    InvalidFee(TokenId, u64),
+        Self::from_string_key_entries(
+            entries
+                .into_iter()
+                .map(|(token_id, (minimum_fee, basis_points))| {
+                    let entry = SerializedFeeEntry {
+                        minimum_fee,
+                        basis_points,
+                    };
+                    (token_id, entry)
+                })
+                .collect(),
+        )
+    }
 
-    /// Token `{0}` is missing from the fee map
-    MissingFee(TokenId),
-}
+    /// Create a fee map from serialized entries keyed by token ids formatted
+    /// as strings.
+    #[cfg(any(feature = "std", feature = "minijson"))]
+    fn from_string_key_entries(
+        entries: BTreeMap<String, SerializedFeeEntry>,
+    ) -> Result<Self, Error> {
+        let mut fees = BTreeMap::new();
+        let mut basis_points = BTreeMap::new();
+        for (token_id, entry) in entries {
+            if let Some(entry_basis_points) = entry.basis_points {
+                basis_points.insert(token_id.clone(), entry_basis_points);
+            }
+            fees.insert(token_id, entry.minimum_fee);
+        }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use alloc::{string::ToString, vec};
+        Self::from_string_keys(fees, basis_points)
+    }
 
-    /// Different fee maps/responder ids should result in different responder
-    /// ids.
-    #[test]
-    fn different_fee_maps_result_in_different_responder_ids() {
-        let fee_map1 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
-        let fee_map2 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 300)]).unwrap();
-        let fee_map3 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(30), 300)]).unwrap();
+    /// Create a fee map from fees and proportional fees keyed by token ids
+    /// formatted as strings.
+    #[cfg(any(feature = "std", feature = "minijson"))]
+    fn from_string_keys(
+        fees: BTreeMap<String, u64>,
+        basis_points: BTreeMap<String, u16>,
+    ) -> Result<Self, Error> {
+        fn parse_token_id(token_id: &str) -> Result<TokenId, Error> {
+            TokenId::from_str(token_id)
+                .map_err(|_| Error::Parse(format!("{} is not a valid token id", token_id)))
+        }
 
-        let responder_id1 = ResponderId("1.2.3.4:5".to_string());
-        let responder_id2 = ResponderId("3.1.3.3:7".to_string());
+        // Distinct keys can name the same token, e.g. "0" and "MOB".
+        let mut map = BTreeMap::new();
+        for (token_id, fee) in fees.iter() {
+            let token_id = parse_token_id(token_id)?;
+            if map.insert(token_id, *fee).is_some() {
+                return Err(Error::DuplicateToken(token_id));
+            }
+        }
+        // As with serde and [FeeMap::from_bytes], the fee ceiling is not
+        // applied, so that a fee map built with a larger ceiling round-trips.
+        let mut fee_map = Self::with_max_fee(map, u64::MAX)?;
 
-        assert_ne!(
-            fee_map1.responder_id(&responder_id1),
-            fee_map2.responder_id(&responder_id1)
-        );
+        let mut with_policy = BTreeSet::new();
+        for (token_id, basis_points) in basis_points.iter() {
+            let token_id = parse_token_id(token_id)?;
+            if !with_policy.insert(token_id) {
+                return Err(Error::DuplicateToken(token_id));
+            }
+            fee_map.set_fee_policy(token_id, FeePolicy::BasisPoints(*basis_points))?;
+        }
 
-        assert_ne!(
-            fee_map1.responder_id(&responder_id1),
-            fee_map3.responder_id(&responder_id1)
-        );
+        Ok(fee_map)
+    }
 
-        assert_ne!(
-            fee_map2.responder_id(&responder_id1),
-            fee_map3.responder_id(&responder_id1)
-        );
+    /// Format the fee map as a JSON object mapping token ids to fees, sorted
+    /// by token id. Proportional fees are formatted as objects with their
+    /// minimum fee and basis points. The output is suitable for
+    /// [FeeMap::from_json] and does not include the digest.
+    pub fn to_json(&self) -> String {
+        let entries = self
+            .map
+            .iter()
+            .map(|(token_id, fee)| match self.basis_points.get(token_id) {
+                None => format!("\"{}\":{}", **token_id, fee),
+                Some(basis_points) => format!(
+                    "\"{}\":{{\"minimum_fee\":{},\"basis_points\":{}}}",
+                    **token_id, fee, basis_points
+                ),
+            })
+            .collect::<Vec<_>>();
+        format!("{{{}}}", entries.join(","))
+    }
 
-        assert_ne!(
-            fee_map1.responder_id(&responder_id1),
-            fee_map1.responder_id(&responder_id2)
-        );
+    /// Encode the fee map in a compact binary form suitable for
+    /// [FeeMap::from_bytes]. The digest is not included.
+    ///
+    /// The encoding is deterministic: a little-endian `u32` count of fees,
+    /// followed by `(token_id: u32, fee: u64)` pairs sorted by token id, then
+    /// a `u32` count of proportional fees, followed by
+    /// `(token_id: u32, basis_points: u16)` pairs sorted by token id.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        encode_fees(&self.map, &self.basis_points)
     }
 
-    /// Invalid fee maps are rejected.
-    #[test]
-    fn invalid_fee_maps_are_rejected() {
-        let test_token_id = TokenId::from(2);
+    /// The canonical encoding of the fee map, e.g. for signing with an
+    /// operator key. This is the encoding of [FeeMap::to_bytes], and is what
+    /// the digest hashes under [DigestVersion::V3], so once
+    /// [FeeMap::recompute_digest] has switched a fee map to V3 a signature over
+    /// these bytes covers exactly what is digested.
+    pub fn canonical_bytes(&self) -> Vec<u8> {
+        self.to_bytes()
+    }
 
-        // Missing MOB is not allowed
-        assert_eq!(
-            FeeMap::is_valid_map(&BTreeMap::default()),
-            Err(Error::MissingFee(Mob::ID)),
-        );
+    /// Decode a fee map produced by [FeeMap::to_bytes]. The decoded fees are
+    /// validated as if they had been deserialized, and entries must be sorted
+    /// by token id.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = FeeMapBytesReader(bytes);
+        let map = reader.read_entries(FeeMapBytesReader::read_u64)?;
+        let basis_points = reader.read_entries(FeeMapBytesReader::read_u16)?;
+        if !reader.0.is_empty() {
+            return Err(Error::Parse("trailing bytes after fee map".into()));
+        }
 
-        assert_eq!(
-            FeeMap::is_valid_map(&BTreeMap::from_iter(vec![(test_token_id, 100)])),
-            Err(Error::MissingFee(Mob::ID)),
+        let mut fee_map = Self::with_max_fee(map, u64::MAX)?;
+        for (token_id, basis_points) in basis_points {
+            fee_map.set_fee_policy(token_id, FeePolicy::BasisPoints(basis_points))?;
+        }
+        Ok(fee_map)
+    }
+
+    /// Append the fee map digest to an existing responder id, producing a
+    /// responder id that is unique to the current fee configuration.
+    pub fn responder_id(&self, responder_id: &ResponderId) -> ResponderId {
+        ResponderId(format!("{}-{}", responder_id.0, self.digest()))
+    }
+
+    /// Get the digest of the fee map, formatted as a hex string.
+    pub fn digest(&self) -> &str {
+        debug_assert_eq!(
+            Ok(&self.cached_digest),
+            self.calc_own_digest_bytes(&self.map, &self.basis_points)
+                .map(hex::encode)
+                .as_ref(),
+            "cached fee map digest is stale"
         );
+        &self.cached_digest
+    }
 
-        // All fees must be >0
-        assert_eq!(
-            FeeMap::is_valid_map(&BTreeMap::from_iter(vec![(Mob::ID, 0)])),
-            Err(Error::InvalidFee(Mob::ID, 0)),
+    /// Returns true if `expected_hex` is the digest of this fee map, ignoring
+    /// case.
+    pub fn verify_digest(&self, expected_hex: &str) -> bool {
+        self.digest().eq_ignore_ascii_case(expected_hex)
+    }
+
+    /// Get the digest of the fee map as raw bytes. Unlike [FeeMap::digest],
+    /// this is suitable for comparing digests without allocating.
+    pub fn digest_bytes(&self) -> [u8; 32] {
+        debug_assert_eq!(
+            Ok(self.cached_digest_bytes),
+            self.calc_own_digest_bytes(&self.map, &self.basis_points),
+            "cached fee map digest is stale"
         );
+        self.cached_digest_bytes
+    }
 
-        assert_eq!(
-            FeeMap::is_valid_map(&BTreeMap::from_iter(vec![
-                (Mob::ID, 10),
-                (test_token_id, 0)
-            ])),
-            Err(Error::InvalidFee(test_token_id, 0)),
+    /// The digest scheme used by [FeeMap::digest] and [FeeMap::responder_id].
+    /// This is ignored while the fee map uses a custom digester.
+    pub fn digest_version(&self) -> DigestVersion {
+        self.digest_version
+    }
+
+    /// Switch the digest scheme used by [FeeMap::digest] and
+    /// [FeeMap::responder_id], recomputing the cached digest. This lets nodes
+    /// agree on a digest scheme during an upgrade. Any custom digester is
+    /// dropped. If the digest cannot be computed, the fee map is left
+    /// unchanged.
+    pub fn recompute_digest(&mut self, version: DigestVersion) -> Result<(), Error> {
+        let digest_bytes = calc_digest_bytes(&self.map, &self.basis_points, version)?;
+
+        self.digest_version = version;
+        self.digester = None;
+        self.cached_digest_bytes = digest_bytes;
+        self.cached_digest = hex::encode(digest_bytes);
+        Ok(())
+    }
+
+    /// Check the fee map's internal invariants: MOB has a fee, every fee is
+    /// greater than 0, every proportional fee is for a token with a fee, and
+    /// the cached digest is up to date. This panics if an invariant is
+    /// violated, and does nothing in release builds.
+    pub fn assert_invariants(&self) {
+        debug_assert!(self.map.contains_key(&Mob::ID), "fee map has no MOB fee");
+        debug_assert!(
+            self.map.values().all(|fee| *fee > 0),
+            "fee map has a zero fee"
+        );
+        debug_assert!(
+            self.basis_points
+                .keys()
+                .all(|token_id| self.map.contains_key(token_id)),
+            "fee map has basis points for a token without a fee"
         );
+        debug_assert_eq!(
+            Ok(self.cached_digest_bytes),
+            self.calc_own_digest_bytes(&self.map, &self.basis_points),
+            "cached fee map digest is stale"
+        );
+        debug_assert_eq!(
+            self.cached_digest,
+            hex::encode(self.cached_digest_bytes),
+            "cached fee map digest is stale"
+        );
+    }
+
+    /// Compute the digest of the fee map under a specific digest scheme,
+    /// formatted as a hex string. This allows reproducing digests produced by
+    /// older versions.
+    pub fn digest_with_version(&self, version: DigestVersion) -> Result<String, Error> {
+        calc_digest(&self.map, &self.basis_points, version)
+    }
+
+    /// Compute a digest over only the fees for the given tokens, formatted as
+    /// a hex string. Tokens which are not in the fee map are ignored. This uses
+    /// a different transcript label to [FeeMap::digest], so a filtered digest
+    /// never collides with the digest of a full fee map.
+    pub fn filtered_digest(&self, tokens: &BTreeSet<TokenId>) -> String {
+        let map = self
+            .map
+            .iter()
+            .filter(|(token_id, _fee)| tokens.contains(token_id))
+            .map(|(token_id, fee)| (*token_id, *fee))
+            .collect();
+        let basis_points = self
+            .basis_points
+            .iter()
+            .filter(|(token_id, _basis_points)| tokens.contains(token_id))
+            .map(|(token_id, basis_points)| (*token_id, *basis_points))
+            .collect();
+
+        let mut transcript = MerlinTranscript::new(b"fee_map_filtered");
+        append_fees_to_transcript(&map, &basis_points, b"fee_map", &mut transcript);
+
+        let mut result = [0u8; 32];
+        transcript.extract_digest(&mut result);
+        hex::encode(result)
+    }
+
+    /// Compute a digest of the fees with an alternate digest algorithm, e.g.
+    /// for attestation by a system which does not use Merlin. Only the
+    /// minimum fees are passed to the digester, so fee policies are not
+    /// covered. To use the algorithm for the cached digest, see
+    /// [FeeMap::with_digester].
+    pub fn digest_with<D: FeeMapDigester>(&self, digester: &D) -> [u8; 32] {
+        digester.digest(&self.map)
+    }
+
+    /// Compare the contents of two fee maps, ignoring the cached digest.
+    pub fn content_eq(&self, other: &FeeMap) -> bool {
+        self.map == other.map && self.basis_points == other.basis_points
+    }
+
+    /// Get the minimum fee for a given token id, or None if no fee is set for
+    /// that token. This is the flat fee, and the floor of any proportional fee.
+    pub fn get_minimum_fee_for_token(&self, token_id: &TokenId) -> Option<Fee> {
+        self.map.get(token_id).cloned().map(Fee)
+    }
+
+    /// Get the fee for a given token id when transacting `amount`, or None if
+    /// no fee is set for that token.
+    ///
+    /// For tokens with the default flat fee this is the minimum fee,
+    /// regardless of `amount`. For tokens with a [FeePolicy::BasisPoints]
+    /// policy this is the larger of the proportional fee and the minimum fee.
+    pub fn get_fee_for_token(&self, token_id: &TokenId, amount: u64) -> Option<Fee> {
+        let minimum_fee = self.get_minimum_fee_for_token(token_id)?;
+
+        Some(match self.basis_points.get(token_id) {
+            None => minimum_fee,
+            Some(basis_points) => {
+                let numerator = amount as u128 * *basis_points as u128;
+                let denominator = MAX_BASIS_POINTS as u128;
+                // Since basis points never exceed 100%, this fits in a u64.
+                let proportional_fee = Fee(((numerator + denominator - 1) / denominator) as u64);
+                proportional_fee.max(minimum_fee)
+            }
+        })
+    }
+
+    /// Get the fee policy for a given token id, or None if no fee is set for
+    /// that token.
+    pub fn fee_policy(&self, token_id: &TokenId) -> Option<FeePolicy> {
+        match self.basis_points.get(token_id) {
+            Some(basis_points) => Some(FeePolicy::BasisPoints(*basis_points)),
+            None => self
+                .get_minimum_fee_for_token(token_id)
+                .map(|fee| FeePolicy::Flat(*fee)),
+        }
+    }
+
+    /// Set the fee policy for a token.
+    ///
+    /// A flat policy sets the token's fee, adding the token if needed. A
+    /// basis points policy requires the token to already have a minimum fee.
+    pub fn set_fee_policy(&mut self, token_id: TokenId, policy: FeePolicy) -> Result<(), Error> {
+        match policy {
+            FeePolicy::Flat(fee) => {
+                Self::validate_fee(&token_id, fee)?;
+
+                let mut map = self.map.clone();
+                map.insert(token_id, fee);
+                let mut basis_points = self.basis_points.clone();
+                basis_points.remove(&token_id);
+
+                self.set_contents(map, basis_points)?;
+            }
+            FeePolicy::BasisPoints(basis_points) => {
+                if basis_points == 0 || basis_points > MAX_BASIS_POINTS {
+                    return Err(Error::InvalidBasisPoints(token_id, basis_points));
+                }
+                if !self.contains_token(&token_id) {
+                    return Err(Error::MissingFee(token_id));
+                }
+
+                let mut all_basis_points = self.basis_points.clone();
+                all_basis_points.insert(token_id, basis_points);
+
+                self.set_contents(self.map.clone(), all_basis_points)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Update the fee map with a new one if provided, or reset it to the
+    /// default.
+    ///
+    /// Any fee policies are reset. If this would not change the fee map, the
+    /// digest is not recomputed.
+    pub fn update_or_default(
+        &mut self,
+        minimum_fees: Option<BTreeMap<TokenId, u64>>,
+    ) -> Result<(), Error> {
+        let minimum_fees = match minimum_fees {
+            Some(minimum_fees) => {
+                Self::is_valid_map(&minimum_fees)?;
+                minimum_fees
+            }
+            None => Self::default_map(),
+        };
+
+        if minimum_fees == self.map && self.basis_points.is_empty() {
+            return Ok(());
+        }
+
+        self.set_contents(minimum_fees, BTreeMap::new())
+    }
+
+    /// Replace the fees with `minimum_fees`, resetting any fee policies as
+    /// with [FeeMap::update_or_default]. The update is atomic: the new fees
+    /// are validated and their digest computed before anything is changed,
+    /// so on error the fees, policies and digest are all left as they were.
+    pub fn try_update(&mut self, minimum_fees: BTreeMap<TokenId, u64>) -> Result<(), Error> {
+        self.update_or_default(Some(minimum_fees))
+    }
+
+    /// Like [FeeMap::update_or_default], but returns the fee map as it was
+    /// before the update, so that callers can log, diff or roll back the
+    /// change. On error the fee map is left unchanged.
+    pub fn update_or_default_returning(
+        &mut self,
+        minimum_fees: Option<BTreeMap<TokenId, u64>>,
+    ) -> Result<FeeMap, Error> {
+        let previous = self.clone();
+        self.update_or_default(minimum_fees)?;
+        Ok(previous)
+    }
+
+    /// Apply fee changes proposed by a peer, recording the peer as the origin
+    /// of the update. As with [FeeMap::merge], entries in `minimum_fees`
+    /// replace or add to the existing fees, and the result is validated.
+    pub fn update_from_peer(
+        &mut self,
+        peer: &ResponderId,
+        minimum_fees: BTreeMap<TokenId, u64>,
+    ) -> Result<(), Error> {
+        *self = self.merge(&minimum_fees)?;
+        self.last_updated_by = Some(peer.clone());
+
+        Ok(())
+    }
+
+    /// The peer that proposed the most recent update applied with
+    /// [FeeMap::update_from_peer], if any.
+    pub fn last_updated_by(&self) -> Option<&ResponderId> {
+        self.last_updated_by.as_ref()
+    }
+
+    /// Get the fee for a token known at compile time, falling back to its
+    /// [Token::MINIMUM_FEE] if no fee is set for it, e.g. because the token
+    /// was added after the fee map was configured.
+    pub fn get_fee_or_default<T: Token>(&self) -> Fee {
+        Fee(self.map.get(&T::ID).cloned().unwrap_or(T::MINIMUM_FEE))
+    }
+
+    /// Get the minimum fee for a given token id, or a MissingFee error if no
+    /// fee is set for that token.
+    pub fn get_fee_for_token_or_err(&self, token_id: &TokenId) -> Result<Fee, Error> {
+        self.get_minimum_fee_for_token(token_id)
+            .ok_or(Error::MissingFee(*token_id))
+    }
+
+    /// Get the fees for several tokens at once, in the order given, or a
+    /// MissingFee error for the first token which has no fee.
+    pub fn get_fees(&self, token_ids: &[TokenId]) -> Result<Vec<Fee>, Error> {
+        token_ids
+            .iter()
+            .map(|token_id| self.get_fee_for_token_or_err(token_id))
+            .collect()
+    }
+
+    /// Get whether a fee is configured for the given token id. Unlike
+    /// [FeeMap::get_minimum_fee_for_token], this makes it explicit that a
+    /// missing fee means the token is unsupported, rather than free.
+    pub fn fee_status(&self, token_id: &TokenId) -> FeeStatus {
+        match self.map.get(token_id) {
+            Some(fee) => FeeStatus::Configured(Fee(*fee)),
+            None => FeeStatus::Unsupported,
+        }
+    }
+
+    /// Returns true if a fee is configured for the given token id.
+    pub fn contains_token(&self, token_id: &TokenId) -> bool {
+        self.map.contains_key(token_id)
+    }
+
+    /// Set the fee for a single token, adding the token if it is not already
+    /// present.
+    pub fn set_fee(&mut self, token_id: TokenId, fee: Fee) -> Result<(), Error> {
+        Self::validate_fee(&token_id, *fee)?;
+
+        let mut map = self.map.clone();
+        map.insert(token_id, *fee);
+
+        self.set_contents(map, self.basis_points.clone())
+    }
+
+    /// Multiply the fee for a single token by `numerator / denominator`,
+    /// rounding down. The scaled fee is validated as by [FeeMap::set_fee], so
+    /// e.g. scaling a fee down to zero is an error.
+    pub fn scale_token(
+        &mut self,
+        token_id: &TokenId,
+        numerator: u64,
+        denominator: u64,
+    ) -> Result<(), Error> {
+        if denominator == 0 {
+            return Err(Error::InvalidScale(numerator, denominator));
+        }
+        let fee = self.get_fee_for_token_or_err(token_id)?;
+
+        let scaled = *fee as u128 * numerator as u128 / denominator as u128;
+        let scaled = u64::try_from(scaled).map_err(|_| Error::Overflow(*token_id, *fee))?;
+        self.set_fee(*token_id, Fee(scaled))
+    }
+
+    /// Check that a single fee is valid, without modifying any map. This is
+    /// the check applied by [FeeMap::set_fee].
+    pub fn validate_fee(token_id: &TokenId, fee: u64) -> Result<(), Error> {
+        if fee == 0 {
+            return Err(Error::InvalidFee(*token_id, fee));
+        }
+        if fee > Self::default_max_fee(token_id) {
+            return Err(Error::FeeTooLarge(*token_id, fee));
+        }
+
+        Ok(())
+    }
+
+    /// Reset a single token to its default fee, clearing any proportional fee
+    /// policy for it. The caller supplies the default, since only MOB's
+    /// default is known here.
+    pub fn reset_token_to_default(
+        &mut self,
+        token_id: &TokenId,
+        default_fee: u64,
+    ) -> Result<(), Error> {
+        self.set_fee_policy(*token_id, FeePolicy::Flat(default_fee))
+    }
+
+    /// Reset the MOB fee to [Mob::MINIMUM_FEE].
+    pub fn reset_mob_to_default(&mut self) -> Result<(), Error> {
+        self.reset_token_to_default(&Mob::ID, Mob::MINIMUM_FEE)
+    }
+
+    /// Remove the fee for a single token, returning the removed fee or None
+    /// if the token was not present. MOB cannot be removed.
+    pub fn remove_fee(&mut self, token_id: TokenId) -> Result<Option<u64>, Error> {
+        if token_id == Mob::ID {
+            return Err(Error::CannotRemoveMob);
+        }
+
+        if !self.contains_token(&token_id) {
+            return Ok(None);
+        }
+
+        let mut map = self.map.clone();
+        let old_fee = map.remove(&token_id);
+        let mut basis_points = self.basis_points.clone();
+        basis_points.remove(&token_id);
+
+        self.set_contents(map, basis_points)?;
+
+        Ok(old_fee)
+    }
+
+    /// Edit several fees at once without recomputing the digest after each
+    /// change. The digest is recomputed once, when the returned guard is
+    /// dropped.
+    pub fn edit(&mut self) -> FeeMapEditGuard<'_> {
+        FeeMapEditGuard {
+            fee_map: self,
+            changed: false,
+        }
+    }
+
+    /// Get a read-only view of the fee map, to hand to code which must not
+    /// modify it.
+    pub fn view(&self) -> FeeMapView<'_> {
+        FeeMapView { fee_map: self }
+    }
+
+    /// Get the entry for a single token, for get-or-insert style updates like
+    /// [BTreeMap::entry].
+    pub fn entry(&mut self, token_id: TokenId) -> FeeEntry<'_> {
+        FeeEntry {
+            fee_map: self,
+            token_id,
+        }
+    }
+
+    /// Keep only the tokens for which `f` returns true. If this would drop
+    /// MOB, the fee map is left unchanged and a MissingFee error is returned.
+    pub fn retain<F: FnMut(&TokenId, &u64) -> bool>(&mut self, mut f: F) -> Result<(), Error> {
+        let map: BTreeMap<TokenId, u64> = self
+            .map
+            .iter()
+            .filter(|(token_id, fee)| f(token_id, fee))
+            .map(|(token_id, fee)| (*token_id, *fee))
+            .collect();
+        Self::is_valid_map_with_max_fee(&map, u64::MAX)?;
+
+        if map.len() != self.map.len() {
+            let basis_points = self
+                .basis_points
+                .iter()
+                .filter(|(token_id, _basis_points)| map.contains_key(token_id))
+                .map(|(token_id, basis_points)| (*token_id, *basis_points))
+                .collect();
+
+            self.set_contents(map, basis_points)?;
+        }
+
+        Ok(())
+    }
+
+    /// Produce a new fee map where entries in `overrides` replace matching
+    /// entries in this map, and new token ids are added.
+    ///
+    /// A merge can only add or replace entries, so MOB is never removed by
+    /// it. Fee policies are kept. The result is validated and its digest
+    /// recomputed.
+    pub fn merge(&self, overrides: &BTreeMap<TokenId, u64>) -> Result<FeeMap, Error> {
+        let mut map = self.map.clone();
+        map.extend(overrides.iter().map(|(token_id, fee)| (*token_id, *fee)));
+        Self::is_valid_map(&map)?;
+
+        let mut fee_map = self.clone();
+        fee_map.set_contents(map, self.basis_points.clone())?;
+        Ok(fee_map)
+    }
+
+    /// Produce a new fee map with every fee replaced by `f(token_id, fee)`.
+    /// Fee policies are kept. The result is validated and its digest
+    /// recomputed.
+    pub fn map_fees<F: FnMut(TokenId, u64) -> u64>(&self, mut f: F) -> Result<FeeMap, Error> {
+        let map: BTreeMap<TokenId, u64> = self
+            .map
+            .iter()
+            .map(|(token_id, fee)| (*token_id, f(*token_id, *fee)))
+            .collect();
+        Self::is_valid_map(&map)?;
+
+        let mut fee_map = self.clone();
+        fee_map.set_contents(map, self.basis_points.clone())?;
+        Ok(fee_map)
+    }
+
+    /// Produce a new fee map with every fee clamped to `[min, max]`, e.g. to
+    /// contain a fee-market anomaly. The band must not allow zero fees, and
+    /// the result is validated as by [FeeMap::map_fees].
+    pub fn clamp_fees(&self, min: u64, max: u64) -> Result<FeeMap, Error> {
+        if min == 0 || min > max {
+            return Err(Error::InvalidFeeBand(min, max));
+        }
+
+        self.map_fees(|_token_id, fee| fee.clamp(min, max))
+    }
+
+    /// Produce a new fee map with every fee raised by `bps` basis points, i.e.
+    /// multiplied by `(10000 + bps) / 10000` and rounded down. Fee policies are
+    /// kept, and the result is validated as by [FeeMap::map_fees].
+    pub fn bump_all_fees_by_bps(&self, bps: u16) -> Result<FeeMap, Error> {
+        let map = self
+            .map
+            .iter()
+            .map(|(token_id, fee)| {
+                let denominator = MAX_BASIS_POINTS as u128;
+                let bumped = *fee as u128 * (denominator + bps as u128) / denominator;
+                u64::try_from(bumped)
+                    .map(|bumped| (*token_id, bumped))
+                    .map_err(|_| Error::Overflow(*token_id, *fee))
+            })
+            .collect::<Result<BTreeMap<_, _>, Error>>()?;
+        Self::is_valid_map(&map)?;
+
+        let mut fee_map = self.clone();
+        fee_map.set_contents(map, self.basis_points.clone())?;
+        Ok(fee_map)
+    }
+
+    /// Compute the changes needed to turn this fee map into `other`.
+    pub fn diff(&self, other: &FeeMap) -> FeeMapDiff {
+        let mut diff = FeeMapDiff::default();
+
+        for (token_id, old_fee) in self.map.iter() {
+            match other.map.get(token_id) {
+                None => diff.removed.push(*token_id),
+                Some(new_fee) => {
+                    if new_fee != old_fee {
+                        diff.changed.push((*token_id, *old_fee, *new_fee));
+                    }
+
+                    let old_basis_points = self.basis_points.get(token_id).cloned();
+                    let new_basis_points = other.basis_points.get(token_id).cloned();
+                    if old_basis_points != new_basis_points {
+                        diff.policy_changed
+                            .push((*token_id, old_basis_points, new_basis_points));
+                    }
+                }
+            }
+        }
+
+        diff.added.extend(
+            other
+                .map
+                .keys()
+                .filter(|token_id| !self.map.contains_key(token_id))
+                .cloned(),
+        );
+
+        diff
+    }
+
+    /// Returns true if the MOB fee is the protocol default,
+    /// [Mob::MINIMUM_FEE].
+    pub fn mob_fee_is_default(&self) -> bool {
+        self.get_minimum_fee_for_token(&Mob::ID) == Some(Fee(Mob::MINIMUM_FEE))
+    }
+
+    /// The default fee ceiling for a token, used by [FeeMap::is_valid_map]
+    /// and [FeeMap::validate_fee]. This is [max_fee_for_token] for built-in
+    /// tokens. Other tokens, whose denomination is unknown here, are bounded
+    /// by [DEFAULT_MAXIMUM_FEE] in their smallest unit; use
+    /// [FeeMap::with_max_fee] to accept larger fees.
+    pub fn default_max_fee(token_id: &TokenId) -> u64 {
+        if *token_id == Mob::ID {
+            max_fee_for_token::<Mob>()
+        } else {
+            DEFAULT_MAXIMUM_FEE
+        }
+    }
+
+    /// Check if a given fee map is valid, using [FeeMap::default_max_fee] as
+    /// the ceiling for each token.
+    pub fn is_valid_map(minimum_fees: &BTreeMap<TokenId, u64>) -> Result<(), Error> {
+        Self::is_valid_map_with_ceiling(minimum_fees, Self::default_max_fee)
+    }
+
+    /// Check if a given fee map is valid, using `max_fee` as the ceiling for
+    /// every fee in the map.
+    pub fn is_valid_map_with_max_fee(
+        minimum_fees: &BTreeMap<TokenId, u64>,
+        max_fee: u64,
+    ) -> Result<(), Error> {
+        Self::is_valid_map_with_ceiling(minimum_fees, |_token_id| max_fee)
+    }
+
+    fn is_valid_map_with_ceiling(
+        minimum_fees: &BTreeMap<TokenId, u64>,
+        max_fee: impl Fn(&TokenId) -> u64,
+    ) -> Result<(), Error> {
+        Self::validation_errors(minimum_fees, max_fee)
+            .next()
+            .map_or(Ok(()), Err)
+    }
+
+    /// Check if a given fee map is valid, like [FeeMap::is_valid_map], but
+    /// collect every problem instead of stopping at the first. The errors are
+    /// in the order [FeeMap::is_valid_map] checks for them, so the first is
+    /// the one it would return.
+    pub fn validate_all(minimum_fees: &BTreeMap<TokenId, u64>) -> Result<(), Vec<Error>> {
+        Self::validate_all_with_ceiling(minimum_fees, Self::default_max_fee)
+    }
+
+    /// Check if a given fee map is valid, like
+    /// [FeeMap::is_valid_map_with_max_fee], but collect every problem instead
+    /// of stopping at the first.
+    pub fn validate_all_with_max_fee(
+        minimum_fees: &BTreeMap<TokenId, u64>,
+        max_fee: u64,
+    ) -> Result<(), Vec<Error>> {
+        Self::validate_all_with_ceiling(minimum_fees, |_token_id| max_fee)
+    }
+
+    fn validate_all_with_ceiling(
+        minimum_fees: &BTreeMap<TokenId, u64>,
+        max_fee: impl Fn(&TokenId) -> u64,
+    ) -> Result<(), Vec<Error>> {
+        let errors = Self::validation_errors(minimum_fees, max_fee).collect::<Vec<_>>();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Every problem with a fee map, in the order they are checked for: zero
+    /// fees, fees above their token's ceiling, then a missing MOB fee. This is
+    /// lazy, so taking only the first error skips the remaining checks.
+    fn validation_errors<'a>(
+        minimum_fees: &'a BTreeMap<TokenId, u64>,
+        max_fee: impl Fn(&TokenId) -> u64 + 'a,
+    ) -> impl Iterator<Item = Error> + 'a {
+        // All fees must be greater than 0.
+        let zero_fees = minimum_fees
+            .iter()
+            .filter(|(_token_id, fee)| **fee == 0)
+            .map(|(token_id, fee)| Error::InvalidFee(*token_id, *fee));
+
+        // No fee may exceed its token's ceiling.
+        let large_fees = minimum_fees
+            .iter()
+            .filter(move |(token_id, fee)| **fee > max_fee(token_id))
+            .map(|(token_id, fee)| Error::FeeTooLarge(*token_id, *fee));
+
+        // Must have a minimum fee for MOB.
+        let missing_mob = core::iter::once(Mob::ID)
+            .filter(move |token_id| !minimum_fees.contains_key(token_id))
+            .map(Error::MissingFee);
+
+        zero_fees.chain(large_fees).chain(missing_mob)
+    }
+
+    /// Check if a given fee map is valid, and that no fee is below the floor
+    /// configured for its token in `floors`. MOB has a floor of
+    /// [DEFAULT_MOB_FEE_FLOOR] unless `floors` overrides it, and tokens
+    /// without a floor only need a fee greater than 0.
+    ///
+    /// To enforce the [Token::MINIMUM_FEE] of tokens known at compile time as
+    /// their floors, pass e.g. `[fee_entry::<Mob>()]` or
+    /// `const_fee_entries!(Mob)`.
+    pub fn is_valid_map_with_floors<I: IntoIterator<Item = (TokenId, u64)>>(
+        minimum_fees: &BTreeMap<TokenId, u64>,
+        floors: I,
+    ) -> Result<(), Error> {
+        Self::is_valid_map(minimum_fees)?;
+
+        let mut all_floors = BTreeMap::from_iter([(Mob::ID, DEFAULT_MOB_FEE_FLOOR)]);
+        all_floors.extend(floors);
+
+        if let Some((token_id, fee, floor)) = minimum_fees.iter().find_map(|(token_id, fee)| {
+            all_floors
+                .get(token_id)
+                .filter(|floor| fee < floor)
+                .map(|floor| (*token_id, *fee, *floor))
+        }) {
+            return Err(Error::FeeBelowFloor(token_id, fee, floor));
+        }
+
+        Ok(())
+    }
+
+    /// Check that every token in the fee map is known to `registry`. MOB is
+    /// always considered known.
+    pub fn validate_against_registry(&self, registry: &TokenRegistry) -> Result<(), Error> {
+        if let Some(token_id) = self
+            .token_ids()
+            .find(|token_id| *token_id != Mob::ID && registry.get(token_id).is_none())
+        {
+            return Err(Error::UnknownToken(token_id));
+        }
+
+        Ok(())
+    }
+
+    /// Iterate over all entries in the fee map.
+    pub fn iter(&self) -> impl Iterator<Item = (&TokenId, &u64)> {
+        self.map.iter()
+    }
+
+    /// Iterate over the token ids in the fee map, in sorted order.
+    pub fn token_ids(&self) -> impl Iterator<Item = TokenId> + '_ {
+        self.map.keys().cloned()
+    }
+
+    /// Iterate over the tokens whose fee is strictly greater than `threshold`,
+    /// in sorted order.
+    pub fn fees_above(&self, threshold: u64) -> impl Iterator<Item = (TokenId, u64)> + '_ {
+        self.map
+            .iter()
+            .filter(move |(_token_id, fee)| **fee > threshold)
+            .map(|(token_id, fee)| (*token_id, *fee))
+    }
+
+    /// Count the fees falling in each bucket defined by the sorted
+    /// `boundaries`. For `n` boundaries there are `n + 1` buckets: fees below
+    /// the first boundary, fees from each boundary up to (but excluding) the
+    /// next, and fees at or above the last boundary.
+    pub fn fee_histogram(&self, boundaries: &[u64]) -> Vec<usize> {
+        let mut counts = vec![0; boundaries.len() + 1];
+        for fee in self.map.values() {
+            counts[boundaries.partition_point(|boundary| boundary <= fee)] += 1;
+        }
+        counts
+    }
+
+    /// The sum of the minimum fees of all tokens, or None if it overflows.
+    pub fn checked_total(&self) -> Option<Fee> {
+        self.map
+            .values()
+            .try_fold(Fee(0), |total, fee| total.checked_add(Fee(*fee)))
+    }
+
+    /// Returns true if every token with a fee in `other` also has a fee in
+    /// this fee map. Fee values are not compared.
+    pub fn is_superset_of(&self, other: &FeeMap) -> bool {
+        other
+            .token_ids()
+            .all(|token_id| self.contains_token(&token_id))
+    }
+
+    /// The token ids that have a fee in both this fee map and `other`.
+    pub fn common_tokens(&self, other: &FeeMap) -> BTreeSet<TokenId> {
+        self.token_ids()
+            .filter(|token_id| other.contains_token(token_id))
+            .collect()
+    }
+
+    /// The token ids that have a fee in this fee map, `other`, or both.
+    pub fn union_tokens(&self, other: &FeeMap) -> BTreeSet<TokenId> {
+        self.token_ids().chain(other.token_ids()).collect()
+    }
+
+    /// The change in fee from this fee map to `other`, as
+    /// `(token_id, other_fee - self_fee)`, for each token with a fee in both,
+    /// sorted by token id.
+    pub fn fee_deltas(&self, other: &FeeMap) -> Vec<(TokenId, i128)> {
+        self.map
+            .iter()
+            .filter_map(|(token_id, fee)| {
+                other
+                    .map
+                    .get(token_id)
+                    .map(|other_fee| (*token_id, *other_fee as i128 - *fee as i128))
+            })
+            .collect()
+    }
+
+    /// The entries of the fee map as owned pairs, sorted by token id. This is
+    /// the same order in which entries are digested.
+    pub fn as_sorted_vec(&self) -> Vec<(TokenId, u64)> {
+        self.map
+            .iter()
+            .map(|(token_id, fee)| (*token_id, *fee))
+            .collect()
+    }
+
+    /// The number of tokens that have a fee configured.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns true if no token has a fee configured.
+    ///
+    /// Fee maps built by the validated constructors always contain at least
+    /// MOB, so this can only be true for a map constructed some other way.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Capture the current fees and digest, so they can later be restored
+    /// with [FeeMap::restore].
+    pub fn snapshot(&self) -> FeeMapSnapshot {
+        FeeMapSnapshot {
+            map: self.map.clone(),
+            basis_points: self.basis_points.clone(),
+            cached_digest: self.cached_digest.clone(),
+            cached_digest_bytes: self.cached_digest_bytes,
+            digest_version: self.digest_version,
+            digester: self.digester.clone(),
+            last_updated_by: self.last_updated_by.clone(),
+        }
+    }
+
+    /// Roll back to a previously captured snapshot. The digest is restored
+    /// as-is, without being recomputed.
+    pub fn restore(&mut self, snapshot: FeeMapSnapshot) {
+        self.map = snapshot.map;
+        self.basis_points = snapshot.basis_points;
+        self.cached_digest = snapshot.cached_digest;
+        self.cached_digest_bytes = snapshot.cached_digest_bytes;
+        self.digest_version = snapshot.digest_version;
+        self.digester = snapshot.digester;
+        self.last_updated_by = snapshot.last_updated_by;
+    }
+
+    /// An approximation of the memory used by this fee map, in bytes. This
+    /// counts the size of each map entry and of the cached digest, but not
+    /// allocator or tree node overhead.
+    pub fn approx_memory_size(&self) -> usize {
+        size_of::<Self>()
+            + self.map.len() * size_of::<(TokenId, u64)>()
+            + self.basis_points.len() * size_of::<(TokenId, u16)>()
+            + self.cached_digest.capacity()
+            + self
+                .last_updated_by
+                .as_ref()
+                .map_or(0, |responder_id| responder_id.0.capacity())
+    }
+
+    /// Helper method for constructing the default fee map.
+    pub fn default_map() -> BTreeMap<TokenId, u64> {
+        BTreeMap::from_iter([fee_entry::<Mob>()])
+    }
+}
+
+/// The fee map entry for a token known at compile time, i.e. its id and
+/// minimum fee.
+pub fn fee_entry<T: Token>() -> (TokenId, u64) {
+    (T::ID, T::MINIMUM_FEE)
+}
+
+/// The default fee ceiling for a token known at compile time, in its smallest
+/// denomination: 1000 whole tokens, or the token's whole [Token::MAX_SUPPLY]
+/// if that is smaller. Saturates at `u64::MAX`.
+pub fn max_fee_for_token<T: Token>() -> u64 {
+    let whole_tokens = T::MAX_SUPPLY.map_or(DEFAULT_MAXIMUM_FEE_WHOLE_TOKENS, |max_supply| {
+        max_supply.min(DEFAULT_MAXIMUM_FEE_WHOLE_TOKENS)
+    });
+    10u64
+        .checked_pow(T::DECIMALS.into())
+        .and_then(|scale| whole_tokens.checked_mul(scale))
+        .unwrap_or(u64::MAX)
+}
+
+/// Estimate the fee for a transaction of the given token with `num_inputs`
+/// inputs and `num_outputs` outputs.
+///
+/// A transaction with one input and one output pays exactly the minimum fee.
+/// Each additional input adds [PER_INPUT_SURCHARGE_BASIS_POINTS] and each
+/// additional output adds [PER_OUTPUT_SURCHARGE_BASIS_POINTS] of the minimum
+/// fee, rounded up. The estimate saturates at `u64::MAX`.
+pub fn estimate_fee(
+    fee_map: &FeeMap,
+    token_id: &TokenId,
+    num_inputs: usize,
+    num_outputs: usize,
+) -> Result<Fee, Error> {
+    let minimum_fee = *fee_map.get_fee_for_token_or_err(token_id)? as u128;
+
+    let extra_inputs = num_inputs.saturating_sub(1) as u128;
+    let extra_outputs = num_outputs.saturating_sub(1) as u128;
+    let surcharge_basis_points = extra_inputs
+        .saturating_mul(PER_INPUT_SURCHARGE_BASIS_POINTS as u128)
+        .saturating_add(extra_outputs.saturating_mul(PER_OUTPUT_SURCHARGE_BASIS_POINTS as u128));
+
+    let denominator = MAX_BASIS_POINTS as u128;
+    let surcharge = minimum_fee
+        .saturating_mul(surcharge_basis_points)
+        .saturating_add(denominator - 1)
+        / denominator;
+
+    Ok(Fee(
+        u64::try_from(minimum_fee.saturating_add(surcharge)).unwrap_or(u64::MAX)
+    ))
+}
+
+/// Split a responder id produced by [FeeMap::responder_id] into the base
+/// responder id and the fee map digest.
+///
+/// Returns None if the responder id does not end in `-` followed by a 64
+/// character hex digest.
+pub fn split_fee_digest(responder_id: &ResponderId) -> Option<(ResponderId, String)> {
+    let (base, digest) = responder_id.0.rsplit_once('-')?;
+    if digest.len() != 64 || !digest.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    Some((ResponderId(base.into()), digest.into()))
+}
+
+/// A guard for editing a [FeeMap], created by [FeeMap::edit]. The fee map's
+/// digest is recomputed when the guard is dropped, so the digest can never be
+/// observed out of date.
+pub struct FeeMapEditGuard<'a> {
+    fee_map: &'a mut FeeMap,
+    changed: bool,
+}
+
+impl<'a> FeeMapEditGuard<'a> {
+    /// Set the fee for a single token, as with [FeeMap::set_fee].
+    pub fn set(&mut self, token_id: TokenId, fee: u64) -> Result<(), Error> {
+        FeeMap::validate_fee(&token_id, fee)?;
+
+        if self.fee_map.map.insert(token_id, fee) != Some(fee) {
+            self.changed = true;
+        }
+        Ok(())
+    }
+
+    /// Remove the fee for a single token, as with [FeeMap::remove_fee].
+    pub fn remove(&mut self, token_id: TokenId) -> Result<Option<u64>, Error> {
+        if token_id == Mob::ID {
+            return Err(Error::CannotRemoveMob);
+        }
+
+        let old_fee = self.fee_map.map.remove(&token_id);
+        if old_fee.is_some() {
+            self.fee_map.basis_points.remove(&token_id);
+            self.changed = true;
+        }
+        Ok(old_fee)
+    }
+}
+
+impl Drop for FeeMapEditGuard<'_> {
+    fn drop(&mut self) {
+        if self.changed {
+            let digest_bytes = self
+                .fee_map
+                .calc_own_digest_bytes(&self.fee_map.map, &self.fee_map.basis_points)
+                .expect("fee map digest can always be computed");
+            self.fee_map.cached_digest_bytes = digest_bytes;
+            self.fee_map.cached_digest = hex::encode(digest_bytes);
+        }
+    }
+}
+
+/// A read-only view of a [FeeMap], created by [FeeMap::view]. Only methods
+/// which read the fee map are exposed.
+#[derive(Clone, Copy, Debug)]
+pub struct FeeMapView<'a> {
+    fee_map: &'a FeeMap,
+}
+
+impl<'a> FeeMapView<'a> {
+    /// Get the minimum fee for a given token id, as with
+    /// [FeeMap::get_minimum_fee_for_token].
+    pub fn get_minimum_fee_for_token(&self, token_id: &TokenId) -> Option<Fee> {
+        self.fee_map.get_minimum_fee_for_token(token_id)
+    }
+
+    /// Get the fee for a given token id when transacting `amount`, as with
+    /// [FeeMap::get_fee_for_token].
+    pub fn get_fee_for_token(&self, token_id: &TokenId, amount: u64) -> Option<Fee> {
+        self.fee_map.get_fee_for_token(token_id, amount)
+    }
+
+    /// Iterate over the fees, sorted by token id.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a TokenId, &'a u64)> {
+        self.fee_map.iter()
+    }
+
+    /// The number of tokens with a fee.
+    pub fn len(&self) -> usize {
+        self.fee_map.len()
+    }
+
+    /// Returns true if no token has a fee.
+    pub fn is_empty(&self) -> bool {
+        self.fee_map.is_empty()
+    }
+
+    /// Get the digest of the fee map, formatted as a hex string.
+    pub fn digest(&self) -> &'a str {
+        self.fee_map.digest()
+    }
+}
+
+impl<'a> From<&'a FeeMap> for FeeMapView<'a> {
+    fn from(fee_map: &'a FeeMap) -> Self {
+        fee_map.view()
+    }
+}
+
+/// A single token's entry in a [FeeMap], created by [FeeMap::entry].
+pub struct FeeEntry<'a> {
+    fee_map: &'a mut FeeMap,
+    token_id: TokenId,
+}
+
+impl<'a> FeeEntry<'a> {
+    /// The token id of this entry.
+    pub fn key(&self) -> &TokenId {
+        &self.token_id
+    }
+
+    /// Get the fee for this token, first setting it to `fee` if the token has
+    /// no fee. The fee is validated as by [FeeMap::set_fee], and the digest is
+    /// only recomputed if it is inserted.
+    pub fn or_insert(self, fee: u64) -> Result<u64, Error> {
+        if let Some(existing_fee) = self.fee_map.map.get(&self.token_id) {
+            return Ok(*existing_fee);
+        }
+
+        self.fee_map.set_fee(self.token_id, Fee(fee))?;
+        Ok(fee)
+    }
+}
+
+/// A saved copy of a [FeeMap]'s fees and digest, created by
+/// [FeeMap::snapshot].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FeeMapSnapshot {
+    map: BTreeMap<TokenId, u64>,
+    basis_points: BTreeMap<TokenId, u16>,
+    cached_digest: String,
+    cached_digest_bytes: [u8; 32],
+    digest_version: DigestVersion,
+    digester: Option<SharedDigester>,
+    last_updated_by: Option<ResponderId>,
+}
+
+/// A builder for constructing a validated [FeeMap] from several sources.
+///
+/// The builder starts out with the default MOB fee, unless
+/// [FeeMapBuilder::clear_defaults] is called.
+#[derive(Clone, Debug)]
+pub struct FeeMapBuilder {
+    /// Fees explicitly provided to the builder.
+    fees: BTreeMap<TokenId, u64>,
+
+    /// Whether the default fee map should be used as a starting point.
+    include_defaults: bool,
+}
+
+impl Default for FeeMapBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FeeMapBuilder {
+    /// Create a new builder, seeded with the default fee map.
+    pub fn new() -> Self {
+        Self {
+            fees: BTreeMap::new(),
+            include_defaults: true,
+        }
+    }
+
+    /// Set the fee for a token, replacing any fee previously set for it.
+    pub fn fee(mut self, token_id: TokenId, fee: u64) -> Self {
+        self.fees.insert(token_id, fee);
+        self
+    }
+
+    /// Do not seed the fee map with the default fees.
+    pub fn clear_defaults(mut self) -> Self {
+        self.include_defaults = false;
+        self
+    }
+
+    /// Validate the fees and construct the fee map.
+    pub fn build(self) -> Result<FeeMap, Error> {
+        let mut map = if self.include_defaults {
+            FeeMap::default_map()
+        } else {
+            BTreeMap::new()
+        };
+        map.extend(self.fees);
+
+        FeeMap::try_from(map)
+    }
+}
+
+/// The changes between two fee maps, as produced by [FeeMap::diff].
+/// All lists are sorted by token id.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FeeMapDiff {
+    /// Token ids present only in the new map.
+    pub added: Vec<TokenId>,
+
+    /// Token ids present only in the old map.
+    pub removed: Vec<TokenId>,
+
+    /// Token ids present in both maps with different fees, as
+    /// `(token_id, old_fee, new_fee)`.
+    pub changed: Vec<(TokenId, u64, u64)>,
+
+    /// Token ids present in both maps with different fee policies, as
+    /// `(token_id, old_basis_points, new_basis_points)`, where `None` is a
+    /// flat fee.
+    pub policy_changed: Vec<(TokenId, Option<u16>, Option<u16>)>,
+}
+
+impl FeeMapDiff {
+    /// Returns true if the two fee maps were identical.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.policy_changed.is_empty()
+    }
+}
+
+impl fmt::Display for FeeMapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+
+        let mut separator = "";
+        for token_id in self.added.iter() {
+            write!(f, "{}added {}", separator, token_id)?;
+            separator = ", ";
+        }
+        for token_id in self.removed.iter() {
+            write!(f, "{}removed {}", separator, token_id)?;
+            separator = ", ";
+        }
+        for (token_id, old_fee, new_fee) in self.changed.iter() {
+            write!(
+                f,
+                "{}changed {}: {} -> {}",
+                separator, token_id, old_fee, new_fee
+            )?;
+            separator = ", ";
+        }
+        for (token_id, old_basis_points, new_basis_points) in self.policy_changed.iter() {
+            let policy = |basis_points: &Option<u16>| match basis_points {
+                None => String::from("flat"),
+                Some(basis_points) => format!("{} bps", basis_points),
+            };
+            write!(
+                f,
+                "{}changed {} policy: {} -> {}",
+                separator,
+                token_id,
+                policy(old_basis_points),
+                policy(new_basis_points)
+            )?;
+            separator = ", ";
+        }
+        Ok(())
+    }
+}
+
+/// Wraps a [FeeMap] to enforce a cooldown between reconfigurations, since
+/// rapid fee changes can destabilize the network.
+///
+/// Timestamps are supplied by the caller, in whatever unit `min_interval` is
+/// given in (e.g. seconds since the epoch).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FeeMapController {
+    fee_map: FeeMap,
+    last_updated_at: Option<u64>,
+}
+
+impl FeeMapController {
+    /// Wrap a fee map. The first update is always allowed.
+    pub fn new(fee_map: FeeMap) -> Self {
+        Self {
+            fee_map,
+            last_updated_at: None,
+        }
+    }
+
+    /// The current fee map.
+    pub fn fee_map(&self) -> &FeeMap {
+        &self.fee_map
+    }
+
+    /// The time of the last successful update, if any.
+    pub fn last_updated_at(&self) -> Option<u64> {
+        self.last_updated_at
+    }
+
+    /// Returns true if at least `min_interval` has passed since the last
+    /// update at time `now`.
+    pub fn can_update(&self, now: u64, min_interval: u64) -> bool {
+        match self.last_updated_at {
+            Some(last_updated_at) => now >= last_updated_at.saturating_add(min_interval),
+            None => true,
+        }
+    }
+
+    /// Apply [FeeMap::update_or_default] at time `now`, or fail with
+    /// UpdateTooSoon if less than `min_interval` has passed since the last
+    /// update. An update which leaves the fee map unchanged does not restart
+    /// the cooldown.
+    pub fn update_or_default(
+        &mut self,
+        minimum_fees: Option<BTreeMap<TokenId, u64>>,
+        now: u64,
+        min_interval: u64,
+    ) -> Result<(), Error> {
+        if let Some(last_updated_at) = self.last_updated_at {
+            if !self.can_update(now, min_interval) {
+                return Err(Error::UpdateTooSoon(
+                    last_updated_at,
+                    last_updated_at.saturating_add(min_interval),
+                ));
+            }
+        }
+
+        let previous = self.fee_map.clone();
+        self.fee_map.update_or_default(minimum_fees)?;
+        if self.fee_map != previous {
+            self.last_updated_at = Some(now);
+        }
+
+        Ok(())
+    }
+}
+
+/// Fee maps which take effect at known block heights, e.g. to schedule a fee
+/// change for a future block. Each fee map is in effect from its starting
+/// block until the next one starts.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EpochFeeMap {
+    epochs: BTreeMap<BlockIndex, FeeMap>,
+}
+
+impl EpochFeeMap {
+    /// Create an epoch fee map from fee maps keyed by their starting block.
+    pub fn new(epochs: BTreeMap<BlockIndex, FeeMap>) -> Self {
+        Self { epochs }
+    }
+
+    /// Schedule `fee_map` to take effect at `start_block`, returning the fee
+    /// map previously scheduled for that block, if any.
+    pub fn insert_epoch(&mut self, start_block: BlockIndex, fee_map: FeeMap) -> Option<FeeMap> {
+        self.epochs.insert(start_block, fee_map)
+    }
+
+    /// Get the fee map in effect at `block_index`, i.e. the one with the
+    /// latest starting block at or before it.
+    pub fn fee_map_for_block(&self, block_index: BlockIndex) -> Result<&FeeMap, Error> {
+        self.epochs
+            .range(..=block_index)
+            .next_back()
+            .map(|(_start_block, fee_map)| fee_map)
+            .ok_or(Error::NoFeeMapForBlock(block_index))
+    }
+}
+
+/// The versions of the scheme used to compute a fee map digest. Each version
+/// uses a distinct transcript label, so digests from different versions never
+/// collide.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum DigestVersion {
+    /// The original digest scheme.
+    V1,
+    /// Same layout as V1, with a versioned transcript label.
+    V2,
+    /// Hashes [FeeMap::canonical_bytes] as a single transcript item, so that
+    /// the digest and signatures over the canonical bytes agree. This is
+    /// opt-in, via [FeeMap::recompute_digest].
+    V3,
+}
+
+impl DigestVersion {
+    /// The transcript label used for this version.
+    pub fn label(&self) -> &'static [u8] {
+        match self {
+            DigestVersion::V1 => b"fee_map",
+            DigestVersion::V2 => b"fee_map_v2",
+            DigestVersion::V3 => b"fee_map_v3",
+        }
+    }
+}
+
+/// An algorithm for computing a digest of the minimum fees of a fee map. See
+/// [FeeMap::digest_with].
+pub trait FeeMapDigester {
+    /// Compute the digest of a map of token id to minimum fee.
+    fn digest(&self, map: &BTreeMap<TokenId, u64>) -> [u8; 32];
+}
+
+/// The Merlin digest used for [FeeMap::digest_bytes], unless a fee map is
+/// created with [FeeMap::with_digester]. For fee maps without proportional
+/// fees, this gives the same digest as a fee map using the same
+/// [DigestVersion].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MerlinFeeMapDigester {
+    /// The digest scheme to use.
+    pub version: DigestVersion,
+}
+
+impl Default for MerlinFeeMapDigester {
+    fn default() -> Self {
+        Self {
+            version: FeeMap::DIGEST_VERSION,
+        }
+    }
+}
+
+impl FeeMapDigester for MerlinFeeMapDigester {
+    fn digest(&self, map: &BTreeMap<TokenId, u64>) -> [u8; 32] {
+        merlin_digest_bytes(map, &BTreeMap::new(), self.version)
+    }
+}
+
+/// A custom [FeeMapDigester] shared between clones of a fee map. Digesters
+/// are compared by identity, since the trait does not require `Eq`.
+#[derive(Clone)]
+struct SharedDigester(Arc<dyn FeeMapDigester + Send + Sync>);
+
+impl fmt::Debug for SharedDigester {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedDigester")
+    }
+}
+
+impl PartialEq for SharedDigester {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::as_ptr(&self.0) as *const () == Arc::as_ptr(&other.0) as *const ()
+    }
+}
+
+impl Eq for SharedDigester {}
+
+/// Encode fees and fee policies as described in [FeeMap::to_bytes].
+fn encode_fees(map: &BTreeMap<TokenId, u64>, basis_points: &BTreeMap<TokenId, u16>) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(
+        2 * size_of::<u32>()
+            + map.len() * (size_of::<u32>() + size_of::<u64>())
+            + basis_points.len() * (size_of::<u32>() + size_of::<u16>()),
+    );
+
+    bytes.extend_from_slice(&(map.len() as u32).to_le_bytes());
+    for (token_id, fee) in map {
+        bytes.extend_from_slice(&token_id.to_le_bytes());
+        bytes.extend_from_slice(&fee.to_le_bytes());
+    }
+
+    bytes.extend_from_slice(&(basis_points.len() as u32).to_le_bytes());
+    for (token_id, basis_points) in basis_points {
+        bytes.extend_from_slice(&token_id.to_le_bytes());
+        bytes.extend_from_slice(&basis_points.to_le_bytes());
+    }
+
+    bytes
+}
+
+fn append_fees_to_transcript<DT: DigestTranscript>(
+    map: &BTreeMap<TokenId, u64>,
+    basis_points: &BTreeMap<TokenId, u16>,
+    context: &'static [u8],
+    transcript: &mut DT,
+) {
+    transcript.append_seq_header(context, map.len() * 2);
+    for (token_id, fee) in map {
+        token_id.append_to_transcript(b"token_id", transcript);
+        fee.append_to_transcript(b"fee", transcript);
+    }
+
+    // Fee policies are only appended when some token has a proportional fee,
+    // so that maps using only flat fees keep the same digest. Each entry
+    // records the policy kind as well as its basis points.
+    if !basis_points.is_empty() {
+        transcript.append_seq_header(b"fee_policies", basis_points.len() * 3);
+        for (token_id, basis_points) in basis_points {
+            token_id.append_to_transcript(b"token_id", transcript);
+            transcript.append_bytes(b"fee_policy", b"basis_points");
+            basis_points.append_to_transcript(b"basis_points", transcript);
+        }
+    }
+}
+
+fn calc_digest(
+    map: &BTreeMap<TokenId, u64>,
+    basis_points: &BTreeMap<TokenId, u16>,
+    version: DigestVersion,
+) -> Result<String, Error> {
+    calc_digest_bytes(map, basis_points, version).map(hex::encode)
+}
+
+/// Compute the digest of a fee map. This cannot currently fail, but callers
+/// must handle [Error::DigestFailure] so that future digest schemes can.
+fn calc_digest_bytes(
+    map: &BTreeMap<TokenId, u64>,
+    basis_points: &BTreeMap<TokenId, u16>,
+    version: DigestVersion,
+) -> Result<[u8; 32], Error> {
+    Ok(merlin_digest_bytes(map, basis_points, version))
+}
+
+fn append_fees_with_version<DT: DigestTranscript>(
+    map: &BTreeMap<TokenId, u64>,
+    basis_points: &BTreeMap<TokenId, u16>,
+    version: DigestVersion,
+    context: &'static [u8],
+    transcript: &mut DT,
+) {
+    match version {
+        DigestVersion::V1 | DigestVersion::V2 => {
+            append_fees_to_transcript(map, basis_points, context, transcript)
+        }
+        DigestVersion::V3 => transcript.append_bytes(context, encode_fees(map, basis_points)),
+    }
+}
+
+fn merlin_digest_bytes(
+    map: &BTreeMap<TokenId, u64>,
+    basis_points: &BTreeMap<TokenId, u16>,
+    version: DigestVersion,
+) -> [u8; 32] {
+    let mut transcript = MerlinTranscript::new(version.label());
+    append_fees_with_version(map, basis_points, version, b"fee_map", &mut transcript);
+
+    let mut result = [0u8; 32];
+    transcript.extract_digest(&mut result);
+    result
+}
+
+/// The TOML form of a fee map. Token ids are string keys, since TOML does not
+/// allow integer keys.
+#[cfg(feature = "std")]
+#[derive(Deserialize, Serialize)]
+struct FeeMapToml {
+    fees: BTreeMap<String, u64>,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    basis_points: BTreeMap<String, u16>,
+}
+
+/// Reads the binary form of a [FeeMap] produced by [FeeMap::to_bytes].
+struct FeeMapBytesReader<'a>(&'a [u8]);
+
+impl<'a> FeeMapBytesReader<'a> {
+    fn read_array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        if self.0.len() < N {
+            return Err(Error::Parse("unexpected end of fee map bytes".into()));
+        }
+
+        let (head, tail) = self.0.split_at(N);
+        self.0 = tail;
+        Ok(<[u8; N]>::try_from(head).expect("head has length N"))
+    }
+
+    fn read_u16(&mut self) -> Result<u16, Error> {
+        self.read_array().map(u16::from_le_bytes)
+    }
+
+    fn read_u32(&mut self) -> Result<u32, Error> {
+        self.read_array().map(u32::from_le_bytes)
+    }
+
+    fn read_u64(&mut self) -> Result<u64, Error> {
+        self.read_array().map(u64::from_le_bytes)
+    }
+
+    /// Read a count followed by that many `(token_id, value)` pairs, which
+    /// must be strictly increasing by token id.
+    fn read_entries<V>(
+        &mut self,
+        read_value: impl Fn(&mut Self) -> Result<V, Error>,
+    ) -> Result<BTreeMap<TokenId, V>, Error> {
+        let count = self.read_u32()?;
+
+        let mut entries = BTreeMap::new();
+        for _ in 0..count {
+            let token_id = TokenId::from(self.read_u32()?);
+            let value = read_value(self)?;
+            if entries
+                .keys()
+                .next_back()
+                .map_or(false, |last| *last >= token_id)
+            {
+                return Err(Error::Parse(format!(
+                    "fee map entries are not sorted by token id at token {}",
+                    token_id
+                )));
+            }
+            entries.insert(token_id, value);
+        }
+
+        Ok(entries)
+    }
+}
+
+/// A key in the serialized form of a fee map: either a token id, or a field of
+/// the legacy struct-shaped encoding.
+enum SerializedFeeMapKey {
+    Token(TokenId),
+    LegacyMap,
+    LegacyDigest,
+}
+
+impl<'de> Deserialize<'de> for SerializedFeeMapKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SerializedFeeMapKeyVisitor;
+
+        impl<'de> Visitor<'de> for SerializedFeeMapKeyVisitor {
+            type Value = SerializedFeeMapKey;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a token id")
+            }
+
+            fn visit_u64<E: de::Error>(self, token_id: u64) -> Result<Self::Value, E> {
+                TokenId::try_from(token_id)
+                    .map(SerializedFeeMapKey::Token)
+                    .map_err(|_| E::invalid_value(de::Unexpected::Unsigned(token_id), &self))
+            }
+
+            // Formats such as JSON only have string keys. These are parsed as
+            // with `TokenId::from_str`, so every format accepts the same keys.
+            fn visit_str<E: de::Error>(self, key: &str) -> Result<Self::Value, E> {
+                match key {
+                    "map" => Ok(SerializedFeeMapKey::LegacyMap),
+                    "cached_digest" => Ok(SerializedFeeMapKey::LegacyDigest),
+                    _ => TokenId::from_str(key)
+                        .map(SerializedFeeMapKey::Token)
+                        .map_err(|_| E::invalid_value(de::Unexpected::Str(key), &self)),
+                }
+            }
+        }
+
+        deserializer.deserialize_any(SerializedFeeMapKeyVisitor)
+    }
+}
+
+/// The serialized form of a single fee map entry. Flat fees serialize as a
+/// bare integer, and proportional fees as a struct carrying both the minimum
+/// fee and the basis points.
+struct SerializedFeeEntry {
+    minimum_fee: u64,
+    basis_points: Option<u16>,
+}
+
+impl Serialize for SerializedFeeEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.basis_points {
+            None => serializer.serialize_u64(self.minimum_fee),
+            Some(basis_points) => {
+                let mut state = serializer.serialize_struct("FeeEntry", 2)?;
+                state.serialize_field("minimum_fee", &self.minimum_fee)?;
+                state.serialize_field("basis_points", &basis_points)?;
+                state.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for SerializedFeeEntry {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SerializedFeeEntryVisitor;
+
+        impl<'de> Visitor<'de> for SerializedFeeEntryVisitor {
+            type Value = SerializedFeeEntry;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a fee, or a minimum fee and basis points")
+            }
+
+            fn visit_u64<E: de::Error>(self, minimum_fee: u64) -> Result<Self::Value, E> {
+                Ok(SerializedFeeEntry {
+                    minimum_fee,
+                    basis_points: None,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+                let mut minimum_fee = None;
+                let mut basis_points = None;
+                while let Some(key) = access.next_key::<String>()? {
+                    match key.as_str() {
+                        "minimum_fee" if minimum_fee.is_none() => {
+                            minimum_fee = Some(access.next_value()?)
+                        }
+                        "basis_points" if basis_points.is_none() => {
+                            basis_points = Some(access.next_value()?)
+                        }
+                        "minimum_fee" => return Err(de::Error::duplicate_field("minimum_fee")),
+                        "basis_points" => return Err(de::Error::duplicate_field("basis_points")),
+                        other => {
+                            return Err(de::Error::unknown_field(
+                                other,
+                                &["minimum_fee", "basis_points"],
+                            ))
+                        }
+                    }
+                }
+
+                Ok(SerializedFeeEntry {
+                    minimum_fee: minimum_fee
+                        .ok_or_else(|| de::Error::missing_field("minimum_fee"))?,
+                    basis_points: Some(
+                        basis_points.ok_or_else(|| de::Error::missing_field("basis_points"))?,
+                    ),
+                })
+            }
+        }
+
+        deserializer.deserialize_any(SerializedFeeEntryVisitor)
+    }
+}
+
+/// Fee Map error type.
+#[derive(Clone, Debug, Deserialize, Display, PartialEq, PartialOrd, Serialize)]
+pub enum Error {
+    /// Token `{0}` has invalid fee `{1}`
+    InvalidFee(TokenId, u64),
+
+    /// Token `{0}` is missing from the fee map
+    MissingFee(TokenId),
+
+    /// Token `{0}` has fee `{1}` which exceeds the maximum allowed fee
+    FeeTooLarge(TokenId, u64),
+
+    /// The MOB fee cannot be removed from the fee map
+    CannotRemoveMob,
+
+    /// Failed to parse fee map: {0}
+    Parse(String),
+
+    /// Token `{0}` has invalid basis points `{1}`
+    InvalidBasisPoints(TokenId, u16),
+
+    /// Token `{0}` is not a known token
+    UnknownToken(TokenId),
+
+    /// Token `{0}` appears more than once
+    DuplicateToken(TokenId),
+
+    /// Failed to serialize fee map: {0}
+    Serialize(String),
+
+    /// Failed to compute the fee map digest: {0}
+    DigestFailure(String),
+
+    /// Token `{0}` has fee `{1}` which is below the floor `{2}`
+    FeeBelowFloor(TokenId, u64, u64),
+
+    /// Fee map was last updated at `{0}`, next update allowed at `{1}`
+    UpdateTooSoon(u64, u64),
+
+    /// Fee band [`{0}`, `{1}`] is invalid
+    InvalidFeeBand(u64, u64),
+
+    /// Token `{0}` has fee `{1}` which overflows when adjusted
+    Overflow(TokenId, u64),
+
+    /// No fee map is in effect at block `{0}`
+    NoFeeMapForBlock(BlockIndex),
+
+    /// Cannot scale a fee by `{0}`/`{1}`
+    InvalidScale(u64, u64),
+}
+
+impl Error {
+    /// A stable numeric code for this error, for clients which need to handle
+    /// errors programmatically. Codes are never reused or renumbered, and
+    /// every new variant must be assigned the next unused code.
+    pub fn code(&self) -> u32 {
+        match self {
+            Error::InvalidFee(..) => 1,
+            Error::MissingFee(..) => 2,
+            Error::FeeTooLarge(..) => 3,
+            Error::CannotRemoveMob => 4,
+            Error::Parse(..) => 5,
+            Error::InvalidBasisPoints(..) => 6,
+            Error::UnknownToken(..) => 7,
+            Error::DuplicateToken(..) => 8,
+            Error::Serialize(..) => 9,
+            Error::DigestFailure(..) => 10,
+            Error::FeeBelowFloor(..) => 11,
+            Error::UpdateTooSoon(..) => 12,
+            Error::InvalidFeeBand(..) => 13,
+            Error::Overflow(..) => 14,
+            Error::NoFeeMapForBlock(..) => 15,
+            Error::InvalidScale(..) => 16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use alloc::{string::ToString, vec};
+    use proptest::prelude::*;
+    use rand_core::SeedableRng;
+    use rand_hc::Hc128Rng;
+
+    impl FeeMap {
+        /// Construct a fee map with a digest which does not match its
+        /// contents.
+        fn with_corrupted_digest(map: BTreeMap<TokenId, u64>) -> Self {
+            Self {
+                map,
+                basis_points: BTreeMap::new(),
+                cached_digest: "corrupted".to_string(),
+                cached_digest_bytes: [0u8; 32],
+                digest_version: FeeMap::DIGEST_VERSION,
+                digester: None,
+                last_updated_by: None,
+            }
+        }
+    }
+
+    /// Different fee maps/responder ids should result in different responder
+    /// ids.
+    #[test]
+    fn different_fee_maps_result_in_different_responder_ids() {
+        let fee_map1 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let fee_map2 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 300)]).unwrap();
+        let fee_map3 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(30), 300)]).unwrap();
+
+        let responder_id1 = ResponderId("1.2.3.4:5".to_string());
+        let responder_id2 = ResponderId("3.1.3.3:7".to_string());
+
+        assert_ne!(
+            fee_map1.responder_id(&responder_id1),
+            fee_map2.responder_id(&responder_id1)
+        );
+
+        assert_ne!(
+            fee_map1.responder_id(&responder_id1),
+            fee_map3.responder_id(&responder_id1)
+        );
+
+        assert_ne!(
+            fee_map2.responder_id(&responder_id1),
+            fee_map3.responder_id(&responder_id1)
+        );
+
+        assert_ne!(
+            fee_map1.responder_id(&responder_id1),
+            fee_map1.responder_id(&responder_id2)
+        );
+    }
+
+    /// Identical fee maps have identical digests, and different fee maps have
+    /// different digests.
+    #[test]
+    fn digest_reflects_map_contents() {
+        let fee_map1 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let fee_map2 = FeeMap::try_from_iter([(TokenId::from(2), 2000), (Mob::ID, 100)]).unwrap();
+        let fee_map3 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 300)]).unwrap();
+
+        assert_eq!(fee_map1.digest(), fee_map2.digest());
+        assert_ne!(fee_map1.digest(), fee_map3.digest());
+        assert_eq!(
+            fee_map1.digest(),
+            calc_digest(
+                &BTreeMap::from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]),
+                &BTreeMap::new(),
+                FeeMap::DIGEST_VERSION
+            )
+            .unwrap()
+        );
+    }
+
+    /// Merging overrides replaces existing fees and adds new tokens.
+    #[test]
+    fn merge_overrides_and_adds_fees() {
+        let test_token_id = TokenId::from(2);
+        let base = FeeMap::try_from_iter([(Mob::ID, 100), (test_token_id, 2000)]).unwrap();
+
+        // Override an existing fee.
+        let merged = base
+            .merge(&BTreeMap::from_iter([(test_token_id, 3000)]))
+            .unwrap();
+        assert_eq!(
+            merged,
+            FeeMap::try_from_iter([(Mob::ID, 100), (test_token_id, 3000)]).unwrap()
+        );
+
+        // Add a new token.
+        let merged = base
+            .merge(&BTreeMap::from_iter([(TokenId::from(30), 5)]))
+            .unwrap();
+        assert_eq!(
+            merged,
+            FeeMap::try_from_iter([
+                (Mob::ID, 100),
+                (test_token_id, 2000),
+                (TokenId::from(30), 5)
+            ])
+            .unwrap()
+        );
+
+        // Merging nothing leaves the map unchanged.
+        assert_eq!(base.merge(&BTreeMap::default()).unwrap(), base);
+
+        // Invalid overrides are rejected.
+        assert_eq!(
+            base.merge(&BTreeMap::from_iter([(Mob::ID, 0)])),
+            Err(Error::InvalidFee(Mob::ID, 0))
+        );
+        assert_eq!(
+            base.merge(&BTreeMap::from_iter([(Mob::ID, DEFAULT_MAXIMUM_FEE + 1)])),
+            Err(Error::FeeTooLarge(Mob::ID, DEFAULT_MAXIMUM_FEE + 1))
+        );
+    }
+
+    /// Invalid fee maps are rejected.
+    #[test]
+    fn invalid_fee_maps_are_rejected() {
+        let test_token_id = TokenId::from(2);
+
+        // Missing MOB is not allowed
+        assert_eq!(
+            FeeMap::is_valid_map(&BTreeMap::default()),
+            Err(Error::MissingFee(Mob::ID)),
+        );
+
+        assert_eq!(
+            FeeMap::is_valid_map(&BTreeMap::from_iter(vec![(test_token_id, 100)])),
+            Err(Error::MissingFee(Mob::ID)),
+        );
+
+        // All fees must be >0
+        assert_eq!(
+            FeeMap::is_valid_map(&BTreeMap::from_iter(vec![(Mob::ID, 0)])),
+            Err(Error::InvalidFee(Mob::ID, 0)),
+        );
+
+        assert_eq!(
+            FeeMap::is_valid_map(&BTreeMap::from_iter(vec![
+                (Mob::ID, 10),
+                (test_token_id, 0)
+            ])),
+            Err(Error::InvalidFee(test_token_id, 0)),
+        );
+    }
+
+    /// Fees up to and including the ceiling are accepted, and fees above it
+    /// are rejected.
+    #[test]
+    fn fees_above_ceiling_are_rejected() {
+        let test_token_id = TokenId::from(2);
+
+        assert_eq!(
+            FeeMap::is_valid_map(&BTreeMap::from_iter(vec![(Mob::ID, DEFAULT_MAXIMUM_FEE)])),
+            Ok(()),
+        );
+
+        assert_eq!(
+            FeeMap::is_valid_map(&BTreeMap::from_iter(vec![(
+                Mob::ID,
+                DEFAULT_MAXIMUM_FEE + 1
+            )])),
+            Err(Error::FeeTooLarge(Mob::ID, DEFAULT_MAXIMUM_FEE + 1)),
+        );
+
+        // Tokens whose denomination is unknown are still bounded by default,
+        // unless a larger ceiling is given.
+        let map = BTreeMap::from_iter(vec![(Mob::ID, 10), (test_token_id, u64::MAX)]);
+        assert_eq!(
+            FeeMap::is_valid_map(&map),
+            Err(Error::FeeTooLarge(test_token_id, u64::MAX)),
+        );
+        assert_eq!(
+            FeeMap::try_from(map.clone()),
+            Err(Error::FeeTooLarge(test_token_id, u64::MAX))
+        );
+        assert!(FeeMap::with_max_fee(map, u64::MAX).is_ok());
+
+        // The ceiling can be overridden.
+        let map = BTreeMap::from_iter(vec![(Mob::ID, 1000), (test_token_id, 1001)]);
+        assert_eq!(FeeMap::is_valid_map_with_max_fee(&map, 1001), Ok(()));
+        assert_eq!(
+            FeeMap::is_valid_map_with_max_fee(&map, 1000),
+            Err(Error::FeeTooLarge(test_token_id, 1001)),
+        );
+
+        assert!(FeeMap::with_max_fee(map.clone(), 1001).is_ok());
+        assert_eq!(
+            FeeMap::with_max_fee(map, 1000),
+            Err(Error::FeeTooLarge(test_token_id, 1001)),
+        );
+    }
+
+    /// Diffing fee maps reports added, removed and changed tokens.
+    #[test]
+    fn diff_reports_changes() {
+        let token2 = TokenId::from(2);
+        let token3 = TokenId::from(3);
+        let token4 = TokenId::from(4);
+
+        let old = FeeMap::try_from_iter([(Mob::ID, 100), (token2, 200), (token3, 300)]).unwrap();
+
+        // No change.
+        let diff = old.diff(&old.clone());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "no changes");
+
+        // Add.
+        let new =
+            FeeMap::try_from_iter([(Mob::ID, 100), (token2, 200), (token3, 300), (token4, 400)])
+                .unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            FeeMapDiff {
+                added: vec![token4],
+                ..Default::default()
+            }
+        );
+
+        // Remove.
+        let new = FeeMap::try_from_iter([(Mob::ID, 100), (token3, 300)]).unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            FeeMapDiff {
+                removed: vec![token2],
+                ..Default::default()
+            }
+        );
+
+        // Change.
+        let new = FeeMap::try_from_iter([(Mob::ID, 150), (token2, 200), (token3, 350)]).unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            FeeMapDiff {
+                changed: vec![(Mob::ID, 100, 150), (token3, 300, 350)],
+                ..Default::default()
+            }
+        );
+
+        // Everything at once.
+        let new = FeeMap::try_from_iter([(Mob::ID, 150), (token3, 300), (token4, 400)]).unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            FeeMapDiff {
+                added: vec![token4],
+                removed: vec![token2],
+                changed: vec![(Mob::ID, 100, 150)],
+                policy_changed: vec![],
+            }
+        );
+        assert_eq!(
+            diff.to_string(),
+            format!(
+                "added {}, removed {}, changed {}: 100 -> 150",
+                token4,
+                token2,
+                Mob::ID
+            )
+        );
+
+        // Policy only.
+        let mut new = old.clone();
+        new.set_fee_policy(token2, FeePolicy::BasisPoints(25))
+            .unwrap();
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            FeeMapDiff {
+                policy_changed: vec![(token2, None, Some(25))],
+                ..Default::default()
+            }
+        );
+        assert!(!diff.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            format!("changed {} policy: flat -> 25 bps", token2)
+        );
+        assert_eq!(
+            new.diff(&old).to_string(),
+            format!("changed {} policy: 25 bps -> flat", token2)
+        );
+    }
+
+    /// Setting a single fee adds or updates the token and refreshes the digest.
+    #[test]
+    fn set_fee_adds_and_updates_tokens() {
+        let test_token_id = TokenId::from(2);
+        let mut fee_map = FeeMap::default();
+
+        // Set a new token.
+        fee_map.set_fee(test_token_id, Fee::from(2000)).unwrap();
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&test_token_id),
+            Some(Fee::from(2000))
+        );
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([(Mob::ID, Mob::MINIMUM_FEE), (test_token_id, 2000)]).unwrap()
+        );
+
+        // Update an existing one.
+        fee_map.set_fee(Mob::ID, Fee::from(100)).unwrap();
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([(Mob::ID, 100), (test_token_id, 2000)]).unwrap()
+        );
+
+        // Reject a zero fee, leaving the map untouched.
+        assert_eq!(
+            fee_map.set_fee(Mob::ID, Fee::from(0)),
+            Err(Error::InvalidFee(Mob::ID, 0))
+        );
+        assert_eq!(
+            fee_map.set_fee(Mob::ID, Fee::from(DEFAULT_MAXIMUM_FEE + 1)),
+            Err(Error::FeeTooLarge(Mob::ID, DEFAULT_MAXIMUM_FEE + 1))
+        );
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([(Mob::ID, 100), (test_token_id, 2000)]).unwrap()
+        );
+    }
+
+    #[test]
+    fn try_update_is_atomic() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+        let original = fee_map.clone();
+
+        // Failed updates leave the fees, policies and digest intact.
+        assert_eq!(
+            fee_map.try_update(BTreeMap::from_iter([(Mob::ID, 200), (TokenId::from(2), 0)])),
+            Err(Error::InvalidFee(TokenId::from(2), 0))
+        );
+        assert_eq!(
+            fee_map.try_update(BTreeMap::from_iter([(TokenId::from(2), 3000)])),
+            Err(Error::MissingFee(Mob::ID))
+        );
+        assert_eq!(
+            fee_map.try_update(BTreeMap::from_iter([(Mob::ID, DEFAULT_MAXIMUM_FEE + 1)])),
+            Err(Error::FeeTooLarge(Mob::ID, DEFAULT_MAXIMUM_FEE + 1))
+        );
+        assert_eq!(fee_map, original);
+        assert_eq!(fee_map.digest(), original.digest());
+        assert_eq!(
+            fee_map.fee_policy(&TokenId::from(2)),
+            Some(FeePolicy::BasisPoints(25))
+        );
+        fee_map.assert_invariants();
+
+        // Resetting the policies alone changes the digest, since they are
+        // digested.
+        let minimum_fees = BTreeMap::from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]);
+        assert_eq!(fee_map.try_update(minimum_fees.clone()), Ok(()));
+        assert_eq!(fee_map, FeeMap::try_from(minimum_fees).unwrap());
+        assert_ne!(fee_map.digest(), original.digest());
+
+        // A successful update replaces everything.
+        let minimum_fees = BTreeMap::from_iter([(Mob::ID, 200), (TokenId::from(3), 3000)]);
+        assert_eq!(fee_map.try_update(minimum_fees.clone()), Ok(()));
+        assert_eq!(fee_map, FeeMap::try_from(minimum_fees).unwrap());
+        assert_ne!(fee_map.digest(), original.digest());
+    }
+
+    #[test]
+    fn scale_token() {
+        let test_token_id = TokenId::from(2);
+        let mut fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (test_token_id, 2000)]).unwrap();
+
+        // Doubling.
+        fee_map.scale_token(&test_token_id, 2, 1).unwrap();
+        let expected = FeeMap::try_from_iter([(Mob::ID, 100), (test_token_id, 4000)]).unwrap();
+        assert_eq!(fee_map, expected);
+        assert_eq!(fee_map.digest(), expected.digest());
+
+        // Halving, rounding down.
+        fee_map.scale_token(&Mob::ID, 1, 2).unwrap();
+        fee_map.scale_token(&Mob::ID, 2, 3).unwrap();
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&Mob::ID),
+            Some(Fee::from(33))
+        );
+
+        // A fee scaled down to zero is rejected, leaving the map untouched.
+        let before = fee_map.clone();
+        assert_eq!(
+            fee_map.scale_token(&Mob::ID, 1, 100),
+            Err(Error::InvalidFee(Mob::ID, 0))
+        );
+        assert_eq!(
+            fee_map.scale_token(&Mob::ID, 1, 0),
+            Err(Error::InvalidScale(1, 0))
+        );
+        assert_eq!(
+            fee_map.scale_token(&TokenId::from(3), 2, 1),
+            Err(Error::MissingFee(TokenId::from(3)))
+        );
+        assert_eq!(
+            fee_map.scale_token(&test_token_id, u64::MAX, 1),
+            Err(Error::Overflow(test_token_id, 4000))
+        );
+        assert_eq!(fee_map, before);
+    }
+
+    /// A candidate fee can be checked without a map.
+    #[test]
+    fn validate_fee() {
+        let test_token_id = TokenId::from(2);
+        assert_eq!(FeeMap::validate_fee(&test_token_id, 1), Ok(()));
+        assert_eq!(FeeMap::validate_fee(&Mob::ID, Mob::MINIMUM_FEE), Ok(()));
+        assert_eq!(FeeMap::validate_fee(&Mob::ID, DEFAULT_MAXIMUM_FEE), Ok(()));
+        assert_eq!(
+            FeeMap::validate_fee(&test_token_id, DEFAULT_MAXIMUM_FEE),
+            Ok(())
+        );
+
+        assert_eq!(
+            FeeMap::validate_fee(&test_token_id, 0),
+            Err(Error::InvalidFee(test_token_id, 0))
+        );
+        assert_eq!(
+            FeeMap::validate_fee(&Mob::ID, DEFAULT_MAXIMUM_FEE + 1),
+            Err(Error::FeeTooLarge(Mob::ID, DEFAULT_MAXIMUM_FEE + 1))
+        );
+        assert_eq!(
+            FeeMap::validate_fee(&test_token_id, u64::MAX),
+            Err(Error::FeeTooLarge(test_token_id, u64::MAX))
+        );
+    }
+
+    /// Removing a fee drops the token, except for MOB which is protected.
+    #[test]
+    fn remove_fee_protects_mob() {
+        let test_token_id = TokenId::from(2);
+        let mut fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (test_token_id, 2000)]).unwrap();
+
+        // Remove a present token.
+        assert_eq!(fee_map.remove_fee(test_token_id), Ok(Some(2000)));
+        assert_eq!(fee_map, FeeMap::try_from_iter([(Mob::ID, 100)]).unwrap());
+
+        // Remove an absent token.
+        assert_eq!(fee_map.remove_fee(test_token_id), Ok(None));
+        assert_eq!(fee_map, FeeMap::try_from_iter([(Mob::ID, 100)]).unwrap());
+
+        // MOB cannot be removed.
+        assert_eq!(fee_map.remove_fee(Mob::ID), Err(Error::CannotRemoveMob));
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&Mob::ID),
+            Some(Fee::from(100))
+        );
+    }
+
+    /// The default fee map only contains MOB.
+    #[test]
+    fn default_fee_map_has_one_entry() {
+        let fee_map = FeeMap::default();
+        assert_eq!(fee_map.len(), 1);
+        assert!(!fee_map.is_empty());
+
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        assert_eq!(fee_map.len(), 2);
+    }
+
+    /// Token ids are returned in sorted order.
+    #[test]
+    fn token_ids_are_sorted() {
+        let fee_map = FeeMap::try_from_iter([
+            (TokenId::from(30), 300),
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            fee_map.token_ids().collect::<Vec<_>>(),
+            vec![Mob::ID, TokenId::from(2), TokenId::from(30)]
+        );
+        assert_eq!(
+            fee_map.token_ids().collect::<Vec<_>>(),
+            fee_map
+                .iter()
+                .map(|(token_id, _fee)| *token_id)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    /// Only configured tokens are reported as contained in the map.
+    #[test]
+    fn contains_token_reports_configured_tokens() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        assert!(fee_map.contains_token(&Mob::ID));
+        assert!(fee_map.contains_token(&TokenId::from(2)));
+        assert!(!fee_map.contains_token(&TokenId::from(3)));
+    }
+
+    /// The builder produces validated fee maps.
+    #[test]
+    fn builder_builds_validated_fee_maps() {
+        let token2 = TokenId::from(2);
+        let token3 = TokenId::from(3);
+
+        // Defaults are included.
+        assert_eq!(FeeMapBuilder::new().build().unwrap(), FeeMap::default());
+
+        // Multiple tokens.
+        let fee_map = FeeMapBuilder::new()
+            .fee(token2, 200)
+            .fee(token3, 300)
+            .fee(token2, 250)
+            .build()
+            .unwrap();
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([(Mob::ID, Mob::MINIMUM_FEE), (token2, 250), (token3, 300)])
+                .unwrap()
+        );
+
+        // Without defaults, MOB must be provided explicitly.
+        assert_eq!(
+            FeeMapBuilder::new()
+                .clear_defaults()
+                .fee(token2, 200)
+                .build(),
+            Err(Error::MissingFee(Mob::ID))
+        );
+        let fee_map = FeeMapBuilder::new()
+            .clear_defaults()
+            .fee(Mob::ID, 10)
+            .build()
+            .unwrap();
+        assert_eq!(fee_map, FeeMap::try_from_iter([(Mob::ID, 10)]).unwrap());
+
+        // Invalid fees are rejected.
+        assert_eq!(
+            FeeMapBuilder::new().fee(token2, 0).build(),
+            Err(Error::InvalidFee(token2, 0))
+        );
+    }
+
+    /// Fee maps can be loaded from JSON.
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_json_loads_fee_map() {
+        let fee_map = FeeMap::from_json(r#"{"0": 100, "2": 2000}"#).unwrap();
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap()
+        );
+
+        assert_eq!(
+            FeeMap::from_json(&format!(r#"{{"0": {}}}"#, Mob::MINIMUM_FEE)).unwrap(),
+            FeeMap::default()
+        );
+
+        // The loaded map is validated.
+        assert_eq!(
+            FeeMap::from_json(r#"{"2": 2000}"#),
+            Err(Error::MissingFee(Mob::ID))
+        );
+        assert_eq!(
+            FeeMap::from_json(r#"{"0": 0}"#),
+            Err(Error::InvalidFee(Mob::ID, 0))
+        );
+    }
+
+    /// Malformed JSON is rejected with a parse error.
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_json_rejects_malformed_input() {
+        assert!(matches!(
+            FeeMap::from_json(r#"{"0": 100"#),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(
+            FeeMap::from_json(r#"{"0": "100"}"#),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(
+            FeeMap::from_json(r#"{"0": 100, "mob": 100}"#),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(
+            FeeMap::from_json(r#"[[0, 100]]"#),
+            Err(Error::Parse(_))
+        ));
+    }
+
+    /// Fee maps are formatted as JSON objects sorted by token id.
+    #[test]
+    fn to_json_is_sorted_by_token_id() {
+        let fee_map = FeeMap::try_from_iter([
+            (TokenId::from(10), 1000),
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+        ])
+        .unwrap();
+
+        assert_eq!(fee_map.to_json(), r#"{"0":100,"2":2000,"10":1000}"#);
+    }
+
+    /// Proportional fees are formatted as objects with both the minimum fee
+    /// and the basis points.
+    #[test]
+    fn to_json_includes_basis_points() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        assert_eq!(
+            fee_map.to_json(),
+            r#"{"0":100,"2":{"minimum_fee":2000,"basis_points":25}}"#
+        );
+    }
+
+    /// Fee maps survive a round trip through JSON.
+    #[cfg(feature = "std")]
+    #[test]
+    fn json_round_trip() {
+        let mut with_policy =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        with_policy
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        let fee_maps = [
+            FeeMap::default(),
+            FeeMap::try_from_iter([
+                (TokenId::from(10), 1000),
+                (Mob::ID, 100),
+                (TokenId::from(2), 2000),
+            ])
+            .unwrap(),
+            with_policy,
+            // Fees above the default ceiling still round-trip.
+            FeeMap::with_max_fee(
+                BTreeMap::from_iter([(Mob::ID, DEFAULT_MAXIMUM_FEE + 1)]),
+                u64::MAX,
+            )
+            .unwrap(),
+        ];
+
+        for fee_map in fee_maps.iter() {
+            let recovered = FeeMap::from_json(&fee_map.to_json()).unwrap();
+            assert_eq!(&recovered, fee_map);
+            assert!(recovered.content_eq(fee_map));
+        }
+    }
+
+    #[test]
+    fn get_fee_or_default() {
+        struct TestToken;
+        impl Token for TestToken {
+            const ID: TokenId = TokenId::EUSD;
+            const MINIMUM_FEE: u64 = 1024;
+            const SYMBOL: &'static str = "TEST";
+            const DECIMALS: u8 = 6;
+        }
+
+        // Present: the configured fee is used.
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::EUSD, 2000)]).unwrap();
+        assert_eq!(fee_map.get_fee_or_default::<Mob>(), Fee::from(100));
+        assert_eq!(fee_map.get_fee_or_default::<TestToken>(), Fee::from(2000));
+
+        // Absent: the token's minimum fee is used.
+        let fee_map = FeeMap::default();
+        assert_eq!(
+            fee_map.get_fee_or_default::<Mob>(),
+            Fee::from(Mob::MINIMUM_FEE)
+        );
+        assert_eq!(fee_map.get_fee_or_default::<TestToken>(), Fee::from(1024));
+    }
+
+    /// Looking up a missing fee produces a MissingFee error.
+    #[test]
+    fn get_fee_for_token_or_err_reports_missing_fees() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        assert_eq!(
+            fee_map.get_fee_for_token_or_err(&Mob::ID),
+            Ok(Fee::from(100))
+        );
+        assert_eq!(
+            fee_map.get_fee_for_token_or_err(&TokenId::from(2)),
+            Ok(Fee::from(2000))
+        );
+        assert_eq!(
+            fee_map.get_fee_for_token_or_err(&TokenId::from(3)),
+            Err(Error::MissingFee(TokenId::from(3)))
+        );
+    }
+
+    /// Content comparison ignores the cached digest.
+    #[test]
+    fn content_eq_ignores_digest() {
+        let map = BTreeMap::from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]);
+        let fee_map = FeeMap::try_from(map.clone()).unwrap();
+        let corrupted = FeeMap::with_corrupted_digest(map);
+
+        assert!(fee_map.content_eq(&fee_map.clone()));
+        assert!(fee_map.content_eq(&corrupted));
+        assert_ne!(fee_map, corrupted);
+
+        let other = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 300)]).unwrap();
+        assert!(!fee_map.content_eq(&other));
+    }
+
+    /// A stale cached digest is caught in debug builds.
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "cached fee map digest is stale")]
+    fn stale_digest_is_caught() {
+        let corrupted = FeeMap::with_corrupted_digest(FeeMap::default_map());
+        corrupted.digest();
+    }
+
+    /// Digests computed under different versions of the scheme differ.
+    #[test]
+    fn digest_versions_are_domain_separated() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        assert_eq!(
+            fee_map.digest(),
+            fee_map.digest_with_version(FeeMap::DIGEST_VERSION).unwrap()
+        );
+        assert_ne!(
+            fee_map.digest_with_version(DigestVersion::V1),
+            fee_map.digest_with_version(DigestVersion::V2)
+        );
+        assert_ne!(
+            fee_map.digest_with_version(DigestVersion::V2),
+            fee_map.digest_with_version(DigestVersion::V3)
+        );
+
+        // V1 digests remain reproducible.
+        let mut transcript = MerlinTranscript::new(b"fee_map");
+        transcript.append_seq_header(b"fee_map", 4);
+        Mob::ID.append_to_transcript(b"token_id", &mut transcript);
+        100u64.append_to_transcript(b"fee", &mut transcript);
+        TokenId::from(2).append_to_transcript(b"token_id", &mut transcript);
+        2000u64.append_to_transcript(b"fee", &mut transcript);
+        let mut expected = [0u8; 32];
+        transcript.extract_digest(&mut expected);
+
+        assert_eq!(
+            fee_map.digest_with_version(DigestVersion::V1).unwrap(),
+            hex::encode(expected)
+        );
+
+        // V1 is still the default.
+        assert_eq!(fee_map.digest(), hex::encode(expected));
+    }
+
+    /// The Digestible implementation matches the cached digest.
+    #[test]
+    fn digestible_matches_cached_digest() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        let mut transcript = MerlinTranscript::new(FeeMap::DIGEST_VERSION.label());
+        fee_map.append_to_transcript(b"fee_map", &mut transcript);
+        let mut result = [0u8; 32];
+        transcript.extract_digest(&mut result);
+
+        assert_eq!(hex::encode(result), fee_map.digest());
+    }
+
+    /// The V3 digest, which is opt-in, hashes the canonical bytes.
+    #[test]
+    fn digest_hashes_canonical_bytes() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+        assert_ne!(fee_map.digest_version(), DigestVersion::V3);
+        fee_map.recompute_digest(DigestVersion::V3).unwrap();
+
+        assert_eq!(fee_map.canonical_bytes(), fee_map.to_bytes());
+
+        let mut transcript = MerlinTranscript::new(b"fee_map_v3");
+        transcript.append_bytes(b"fee_map", fee_map.canonical_bytes());
+        let mut expected = [0u8; 32];
+        transcript.extract_digest(&mut expected);
+
+        assert_eq!(fee_map.digest_bytes(), expected);
+        assert_eq!(fee_map.digest(), hex::encode(expected));
+
+        // The Digestible implementation follows the fee map's version.
+        let mut transcript = MerlinTranscript::new(DigestVersion::V3.label());
+        fee_map.append_to_transcript(b"fee_map", &mut transcript);
+        let mut result = [0u8; 32];
+        transcript.extract_digest(&mut result);
+        assert_eq!(result, expected);
+    }
+
+    /// A flat fee and a proportional fee with the same floor have different
+    /// digests, and so different responder ids, under every digest version.
+    #[test]
+    fn fee_policy_kind_is_digested() {
+        let base = ResponderId("node1.test.mobilecoin.com:443".to_string());
+        let flat = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let mut proportional = flat.clone();
+        proportional
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+        let mut other_proportional = flat.clone();
+        other_proportional
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(30))
+            .unwrap();
+
+        for version in [DigestVersion::V1, DigestVersion::V2, DigestVersion::V3] {
+            let mut flat = flat.clone();
+            let mut proportional = proportional.clone();
+            let mut other_proportional = other_proportional.clone();
+            flat.recompute_digest(version).unwrap();
+            proportional.recompute_digest(version).unwrap();
+            other_proportional.recompute_digest(version).unwrap();
+
+            assert_eq!(
+                flat.get_minimum_fee_for_token(&TokenId::from(2)),
+                proportional.get_minimum_fee_for_token(&TokenId::from(2))
+            );
+            assert_ne!(flat.digest(), proportional.digest());
+            assert_ne!(proportional.digest(), other_proportional.digest());
+            assert_ne!(flat.responder_id(&base), proportional.responder_id(&base));
+        }
+    }
+
+    /// Flat fees do not depend on the amount transacted.
+    #[test]
+    fn flat_fee_policy() {
+        let test_token_id = TokenId::from(2);
+        let mut fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (test_token_id, 2000)]).unwrap();
+
+        // Flat is the default.
+        assert_eq!(
+            fee_map.fee_policy(&test_token_id),
+            Some(FeePolicy::Flat(2000))
+        );
+        assert_eq!(fee_map.fee_policy(&TokenId::from(3)), None);
+        for amount in [0, 1, 2000, u64::MAX].iter() {
+            assert_eq!(
+                fee_map.get_fee_for_token(&test_token_id, *amount),
+                Some(Fee::from(2000))
+            );
+        }
+        assert_eq!(fee_map.get_fee_for_token(&TokenId::from(3), 100), None);
+
+        // Setting a flat policy sets the fee.
+        fee_map
+            .set_fee_policy(TokenId::from(3), FeePolicy::Flat(30))
+            .unwrap();
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&TokenId::from(3)),
+            Some(Fee::from(30))
+        );
+        assert_eq!(
+            fee_map.set_fee_policy(TokenId::from(3), FeePolicy::Flat(0)),
+            Err(Error::InvalidFee(TokenId::from(3), 0))
+        );
+    }
+
+    /// Basis point fees are proportional to the amount, rounded up, and never
+    /// less than the minimum fee.
+    #[test]
+    fn basis_points_fee_policy() {
+        let test_token_id = TokenId::from(2);
+        let flat = FeeMap::try_from_iter([(Mob::ID, 100), (test_token_id, 10)]).unwrap();
+        let mut fee_map = flat.clone();
+
+        // 0.25%
+        fee_map
+            .set_fee_policy(test_token_id, FeePolicy::BasisPoints(25))
+            .unwrap();
+        assert_eq!(
+            fee_map.fee_policy(&test_token_id),
+            Some(FeePolicy::BasisPoints(25))
+        );
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&test_token_id),
+            Some(Fee::from(10))
+        );
+
+        // The minimum fee applies to small amounts.
+        assert_eq!(
+            fee_map.get_fee_for_token(&test_token_id, 0),
+            Some(Fee::from(10))
+        );
+        assert_eq!(
+            fee_map.get_fee_for_token(&test_token_id, 4000),
+            Some(Fee::from(10))
+        );
+        // Proportional fees are rounded up.
+        assert_eq!(
+            fee_map.get_fee_for_token(&test_token_id, 4001),
+            Some(Fee::from(11))
+        );
+        assert_eq!(
+            fee_map.get_fee_for_token(&test_token_id, 4400),
+            Some(Fee::from(11))
+        );
+        assert_eq!(
+            fee_map.get_fee_for_token(&test_token_id, 4401),
+            Some(Fee::from(12))
+        );
+        assert_eq!(
+            fee_map.get_fee_for_token(&test_token_id, u64::MAX),
+            Some(Fee::from(46116860184273880))
+        );
+        // Other tokens are unaffected.
+        assert_eq!(
+            fee_map.get_fee_for_token(&Mob::ID, 1_000_000),
+            Some(Fee::from(100))
+        );
+
+        // The policy is part of the digest.
+        assert_ne!(fee_map.digest(), flat.digest());
+        assert!(!fee_map.content_eq(&flat));
+        assert_ne!(fee_map, flat);
+
+        // Reverting to a flat fee restores the original fee map and digest.
+        fee_map
+            .set_fee_policy(test_token_id, FeePolicy::Flat(10))
+            .unwrap();
+        assert_eq!(fee_map, flat);
+
+        // Invalid policies are rejected.
+        assert_eq!(
+            fee_map.set_fee_policy(test_token_id, FeePolicy::BasisPoints(0)),
+            Err(Error::InvalidBasisPoints(test_token_id, 0))
+        );
+        assert_eq!(
+            fee_map.set_fee_policy(test_token_id, FeePolicy::BasisPoints(MAX_BASIS_POINTS + 1)),
+            Err(Error::InvalidBasisPoints(
+                test_token_id,
+                MAX_BASIS_POINTS + 1
+            ))
+        );
+        assert_eq!(
+            fee_map.set_fee_policy(TokenId::from(3), FeePolicy::BasisPoints(25)),
+            Err(Error::MissingFee(TokenId::from(3)))
+        );
+        assert_eq!(fee_map, flat);
+    }
+
+    /// Every configured token must be present in the registry.
+    #[test]
+    fn validate_against_registry() {
+        use mc_transaction_core::TokenInfo;
+
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        let mut registry = TokenRegistry::with_builtin_tokens();
+        assert_eq!(
+            fee_map.validate_against_registry(&registry),
+            Err(Error::UnknownToken(TokenId::from(2)))
+        );
+
+        registry
+            .register(
+                TokenId::from(2),
+                TokenInfo {
+                    symbol: "TEST".into(),
+                    decimals: 6,
+                    minimum_fee: 2000,
+                },
+            )
+            .unwrap();
+        assert_eq!(fee_map.validate_against_registry(&registry), Ok(()));
+
+        // MOB is always known, even to an empty registry.
+        assert_eq!(
+            FeeMap::default().validate_against_registry(&TokenRegistry::new()),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn fees_above_filters_by_threshold() {
+        let fee_map = FeeMap::try_from_iter([
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+            (TokenId::from(3), 50),
+            (TokenId::from(4), 3000),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            fee_map.fees_above(100).collect::<Vec<_>>(),
+            vec![(TokenId::from(2), 2000), (TokenId::from(4), 3000)]
+        );
+        assert_eq!(fee_map.fees_above(0).count(), 4);
+        assert_eq!(fee_map.fees_above(3000).count(), 0);
+    }
+
+    #[test]
+    fn fee_histogram_counts_fees_per_bucket() {
+        let fee_map = FeeMap::try_from_iter([
+            (Mob::ID, 400_000_000),
+            (TokenId::from(2), 1000),
+            (TokenId::from(3), 50),
+            (TokenId::from(4), 999_999),
+            (TokenId::from(5), 1_000_000),
+        ])
+        .unwrap();
+
+        assert_eq!(fee_map.fee_histogram(&[1000, 1_000_000]), vec![1, 2, 2]);
+        assert_eq!(fee_map.fee_histogram(&[]), vec![5]);
+        assert_eq!(fee_map.fee_histogram(&[1, u64::MAX]), vec![0, 5, 0]);
+    }
+
+    /// Fee maps serialize as a flat map of token id to fee, and survive a
+    /// round trip.
+    #[cfg(feature = "std")]
+    #[test]
+    fn serde_round_trip() {
+        let mut with_policy =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        with_policy
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        let fee_maps = [
+            FeeMap::default(),
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap(),
+            // Fees above the default ceiling still round-trip.
+            FeeMap::with_max_fee(
+                BTreeMap::from_iter([(Mob::ID, DEFAULT_MAXIMUM_FEE + 1)]),
+                u64::MAX,
+            )
+            .unwrap(),
+            with_policy,
+        ];
+
+        for fee_map in fee_maps.iter() {
+            let json = serde_json::to_string(fee_map).unwrap();
+            let recovered: FeeMap = serde_json::from_str(&json).unwrap();
+            assert_eq!(&recovered, fee_map);
+            assert_eq!(recovered.digest(), fee_map.digest());
+        }
+
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        assert_eq!(
+            serde_json::to_string(&fee_map).unwrap(),
+            r#"{"0":100,"2":2000}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&fee_maps[3]).unwrap(),
+            r#"{"0":100,"2":{"minimum_fee":2000,"basis_points":25}}"#
+        );
+    }
+
+    /// A hand-written map without a digest deserializes, and invalid maps are
+    /// rejected.
+    #[cfg(feature = "std")]
+    #[test]
+    fn serde_deserializes_hand_written_maps() {
+        let fee_map: FeeMap = serde_json::from_str(r#"{"0": 100, "2": 2000}"#).unwrap();
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap()
+        );
+
+        assert!(serde_json::from_str::<FeeMap>(r#"{"2": 2000}"#).is_err());
+        assert!(serde_json::from_str::<FeeMap>(r#"{"0": 0}"#).is_err());
+        assert!(serde_json::from_str::<FeeMap>(
+            r#"{"0": 100, "2": {"minimum_fee": 2000, "basis_points": 0}}"#
+        )
+        .is_err());
+        assert!(
+            serde_json::from_str::<FeeMap>(r#"{"0": 100, "2": {"minimum_fee": 2000}}"#).is_err()
+        );
+    }
+
+    /// String keys are parsed as token ids in the same way by every format,
+    /// and agree with integer keys.
+    #[cfg(feature = "std")]
+    #[test]
+    fn serde_string_keys_match_token_id_from_str() {
+        let expected = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(42), 2000)]).unwrap();
+
+        let fee_map: FeeMap = serde_json::from_str(r#"{"MOB": 100, "0x2a": 2000}"#).unwrap();
+        assert_eq!(fee_map, expected);
+
+        let string_keys = BTreeMap::from_iter([("MOB", 100u64), ("0x2a", 2000)]);
+        let bytes = mc_util_serial::serialize(&string_keys).unwrap();
+        assert_eq!(
+            mc_util_serial::deserialize::<FeeMap>(&bytes).unwrap(),
+            expected
+        );
+
+        let integer_keys = BTreeMap::from_iter([(0u64, 100u64), (42, 2000)]);
+        let bytes = mc_util_serial::serialize(&integer_keys).unwrap();
+        assert_eq!(
+            mc_util_serial::deserialize::<FeeMap>(&bytes).unwrap(),
+            expected
+        );
+
+        // Token ids which do not fit in a u32 are rejected either way.
+        assert!(serde_json::from_str::<FeeMap>(r#"{"0": 100, "4294967296": 1}"#).is_err());
+        let oversized_keys = BTreeMap::from_iter([(0u64, 100u64), (1 << 32, 1)]);
+        let bytes = mc_util_serial::serialize(&oversized_keys).unwrap();
+        assert!(mc_util_serial::deserialize::<FeeMap>(&bytes).is_err());
+    }
+
+    /// Distinct keys which name the same token are rejected as duplicates,
+    /// rather than one silently overwriting the other.
+    #[cfg(feature = "std")]
+    #[test]
+    fn keys_naming_the_same_token_are_rejected() {
+        let duplicate = Error::DuplicateToken(Mob::ID);
+
+        for json in [
+            r#"{"0": 100, "MOB": 200}"#,
+            r#"{"0": 100, "0x0": 200}"#,
+            r#"{"map": {"0": 100}, "0": 200}"#,
+            r#"{"0": 200, "map": {"0": 100}}"#,
+        ]
+        .iter()
+        {
+            let err = serde_json::from_str::<FeeMap>(json).unwrap_err();
+            assert!(err.to_string().contains(&duplicate.to_string()), "{}", json);
+        }
+
+        assert_eq!(
+            FeeMap::from_json(r#"{"0": 100, "MOB": 200}"#),
+            Err(duplicate.clone())
+        );
+        assert_eq!(
+            FeeMap::from_json(r#"{"0": 100, "0x0": 200}"#),
+            Err(duplicate.clone())
+        );
+        assert_eq!(
+            FeeMap::from_toml("[fees]\n0 = 100\nMOB = 200\n"),
+            Err(duplicate)
+        );
+        assert_eq!(
+            FeeMap::from_toml("[fees]\n0 = 100\n2 = 2000\n\n[basis_points]\n2 = 25\n0x2 = 30\n"),
+            Err(Error::DuplicateToken(TokenId::from(2)))
+        );
+    }
+
+    /// Fee maps in the legacy struct-shaped encoding still deserialize, with
+    /// the stored digest ignored and recomputed.
+    #[cfg(feature = "std")]
+    #[test]
+    fn serde_deserializes_legacy_struct_shaped_maps() {
+        #[derive(Serialize)]
+        struct LegacyFeeMap {
+            map: BTreeMap<TokenId, u64>,
+            cached_digest: String,
+        }
+
+        let legacy = LegacyFeeMap {
+            map: BTreeMap::from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]),
+            cached_digest: "stale".to_string(),
+        };
+        let expected = FeeMap::try_from(legacy.map.clone()).unwrap();
+
+        let json = serde_json::to_string(&legacy).unwrap();
+        assert_eq!(
+            json,
+            r#"{"map":{"0":100,"2":2000},"cached_digest":"stale"}"#
+        );
+        let fee_map: FeeMap = serde_json::from_str(&json).unwrap();
+        assert_eq!(fee_map, expected);
+        assert_eq!(fee_map.digest(), expected.digest());
+
+        // Binary formats with integer keys work too, in either encoding.
+        let bytes = mc_util_serial::serialize(&legacy).unwrap();
+        assert_eq!(
+            mc_util_serial::deserialize::<FeeMap>(&bytes).unwrap(),
+            expected
+        );
+        let bytes = mc_util_serial::serialize(&expected).unwrap();
+        assert_eq!(
+            mc_util_serial::deserialize::<FeeMap>(&bytes).unwrap(),
+            expected
+        );
+
+        // Legacy fee maps are still validated.
+        assert!(
+            serde_json::from_str::<FeeMap>(r#"{"map":{"2":2000},"cached_digest":"stale"}"#)
+                .is_err()
+        );
+        assert!(serde_json::from_str::<FeeMap>(r#"{"fees":{"0":100}}"#).is_err());
+    }
+
+    /// One input and one output pays the minimum fee, and each extra input or
+    /// output adds a surcharge.
+    #[test]
+    fn estimate_fee_scales_with_inputs_and_outputs() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 1000), (TokenId::from(2), 15)]).unwrap();
+
+        assert_eq!(estimate_fee(&fee_map, &Mob::ID, 1, 1), Ok(Fee::from(1000)));
+        assert_eq!(
+            estimate_fee(&fee_map, &TokenId::from(2), 1, 1),
+            Ok(Fee::from(15))
+        );
+
+        // 3 extra inputs at 10% each, 1 extra output at 5%.
+        assert_eq!(estimate_fee(&fee_map, &Mob::ID, 4, 2), Ok(Fee::from(1350)));
+        // Surcharges are rounded up.
+        assert_eq!(
+            estimate_fee(&fee_map, &TokenId::from(2), 2, 1),
+            Ok(Fee::from(17))
+        );
+
+        assert_eq!(
+            estimate_fee(&fee_map, &Mob::ID, usize::MAX, usize::MAX),
+            Ok(Fee::from(u64::MAX))
+        );
+    }
+
+    #[test]
+    fn estimate_fee_rejects_unknown_tokens() {
+        assert_eq!(
+            estimate_fee(&FeeMap::default(), &TokenId::from(2), 1, 1),
+            Err(Error::MissingFee(TokenId::from(2)))
+        );
+    }
+
+    #[test]
+    fn checked_total_detects_overflow() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        assert_eq!(fee_map.checked_total(), Some(Fee::from(2100)));
+
+        let fee_map = FeeMap::with_max_fee(
+            BTreeMap::from_iter([(Mob::ID, u64::MAX), (TokenId::from(2), 1)]),
+            u64::MAX,
+        )
+        .unwrap();
+        assert_eq!(fee_map.checked_total(), None);
+    }
+
+    #[test]
+    fn sample_is_valid_and_contains_mob() {
+        let mut rng: Hc128Rng = SeedableRng::from_seed([1u8; 32]);
+        for num_tokens in [0, 1, 5, 100] {
+            let fee_map = FeeMap::sample(num_tokens, &mut rng);
+            assert_eq!(fee_map.len(), num_tokens + 1);
+            assert!(fee_map.contains_token(&Mob::ID));
+            assert_eq!(FeeMap::is_valid_map(&fee_map.map), Ok(()));
+            fee_map.assert_invariants();
+        }
+    }
+
+    #[test]
+    fn try_from_iter_strict_rejects_duplicates() {
+        assert_eq!(
+            FeeMap::try_from_iter_strict([(TokenId::from(2), 2000), (Mob::ID, 100)]),
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)])
+        );
+
+        assert_eq!(
+            FeeMap::try_from_iter_strict([
+                (Mob::ID, 100),
+                (TokenId::from(2), 2000),
+                (TokenId::from(2), 3000),
+            ]),
+            Err(Error::DuplicateToken(TokenId::from(2)))
+        );
+
+        // Duplicates are reported even if the fees agree.
+        assert_eq!(
+            FeeMap::try_from_iter_strict([(Mob::ID, 100), (Mob::ID, 100)]),
+            Err(Error::DuplicateToken(Mob::ID))
+        );
+    }
+
+    /// Restoring a snapshot undoes any changes made since it was taken.
+    #[test]
+    fn snapshot_and_restore() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let original = fee_map.clone();
+        let snapshot = fee_map.snapshot();
+
+        fee_map.set_fee(TokenId::from(3), Fee::from(30)).unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+        fee_map.remove_fee(TokenId::from(2)).unwrap();
+        assert_ne!(fee_map.digest(), original.digest());
+
+        fee_map.restore(snapshot);
+        assert_eq!(fee_map, original);
+        assert_eq!(fee_map.digest(), original.digest());
+    }
+
+    #[test]
+    fn mob_fee_is_default() {
+        assert!(FeeMap::default().mob_fee_is_default());
+        assert!(
+            FeeMap::try_from_iter([(Mob::ID, Mob::MINIMUM_FEE), (TokenId::from(2), 2000)])
+                .unwrap()
+                .mob_fee_is_default()
+        );
+        assert!(!FeeMap::try_from_iter([(Mob::ID, Mob::MINIMUM_FEE + 1)])
+            .unwrap()
+            .mob_fee_is_default());
+    }
+
+    /// Error messages are part of the API and should not change by accident.
+    #[test]
+    fn error_display() {
+        let token_id = TokenId::from(2);
+        let cases = [
+            (
+                Error::InvalidFee(token_id, 0),
+                "Token `2` has invalid fee `0`",
+            ),
+            (
+                Error::MissingFee(token_id),
+                "Token `2` is missing from the fee map",
+            ),
+            (
+                Error::FeeTooLarge(token_id, 5),
+                "Token `2` has fee `5` which exceeds the maximum allowed fee",
+            ),
+            (
+                Error::CannotRemoveMob,
+                "The MOB fee cannot be removed from the fee map",
+            ),
+            (
+                Error::Parse("oops".to_string()),
+                "Failed to parse fee map: oops",
+            ),
+            (
+                Error::InvalidBasisPoints(token_id, 0),
+                "Token `2` has invalid basis points `0`",
+            ),
+            (
+                Error::UnknownToken(token_id),
+                "Token `2` is not a known token",
+            ),
+            (
+                Error::DuplicateToken(token_id),
+                "Token `2` appears more than once",
+            ),
+            (
+                Error::Serialize("oops".to_string()),
+                "Failed to serialize fee map: oops",
+            ),
+            (
+                Error::DigestFailure("oops".to_string()),
+                "Failed to compute the fee map digest: oops",
+            ),
+            (
+                Error::FeeBelowFloor(token_id, 1, 2),
+                "Token `2` has fee `1` which is below the floor `2`",
+            ),
+            (
+                Error::UpdateTooSoon(1, 2),
+                "Fee map was last updated at `1`, next update allowed at `2`",
+            ),
+            (
+                Error::InvalidFeeBand(2, 1),
+                "Fee band [`2`, `1`] is invalid",
+            ),
+            (
+                Error::Overflow(token_id, 5),
+                "Token `2` has fee `5` which overflows when adjusted",
+            ),
+            (
+                Error::NoFeeMapForBlock(5),
+                "No fee map is in effect at block `5`",
+            ),
+            (Error::InvalidScale(1, 0), "Cannot scale a fee by `1`/`0`"),
+        ];
+
+        for (error, message) in cases.iter() {
+            assert_eq!(&error.to_string(), message);
+        }
+    }
+
+    /// Error codes are stable, and must never be reassigned.
+    #[test]
+    fn error_codes_are_stable() {
+        let token_id = TokenId::from(2);
+        let cases = [
+            (Error::InvalidFee(token_id, 0), 1),
+            (Error::MissingFee(token_id), 2),
+            (Error::FeeTooLarge(token_id, 5), 3),
+            (Error::CannotRemoveMob, 4),
+            (Error::Parse("oops".to_string()), 5),
+            (Error::InvalidBasisPoints(token_id, 0), 6),
+            (Error::UnknownToken(token_id), 7),
+            (Error::DuplicateToken(token_id), 8),
+            (Error::Serialize("oops".to_string()), 9),
+            (Error::DigestFailure("oops".to_string()), 10),
+            (Error::FeeBelowFloor(TokenId::from(2), 1, 2), 11),
+            (Error::UpdateTooSoon(1, 2), 12),
+            (Error::InvalidFeeBand(2, 1), 13),
+            (Error::Overflow(token_id, 5), 14),
+            (Error::NoFeeMapForBlock(5), 15),
+            (Error::InvalidScale(1, 0), 16),
+        ];
+
+        for (error, code) in cases.iter() {
+            assert_eq!(error.code(), *code, "{:?}", error);
+        }
+    }
+
+    /// The raw digest matches the hex digest.
+    #[test]
+    fn digest_bytes_matches_digest() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        assert_eq!(hex::encode(fee_map.digest_bytes()), fee_map.digest());
+
+        fee_map.set_fee(TokenId::from(3), Fee::from(30)).unwrap();
+        assert_eq!(hex::encode(fee_map.digest_bytes()), fee_map.digest());
+
+        assert_eq!(
+            hex::encode(FeeMap::default().digest_bytes()),
+            FeeMap::default().digest()
+        );
+    }
+
+    /// Updates from peers record the peer, without affecting the digest.
+    #[test]
+    fn update_from_peer_records_origin() {
+        let peer = ResponderId("peer1:8443".to_string());
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        assert_eq!(fee_map.last_updated_by(), None);
+
+        fee_map
+            .update_from_peer(&peer, BTreeMap::from_iter([(TokenId::from(2), 3000)]))
+            .unwrap();
+        assert_eq!(fee_map.last_updated_by(), Some(&peer));
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&Mob::ID),
+            Some(Fee::from(100))
+        );
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&TokenId::from(2)),
+            Some(Fee::from(3000))
+        );
+
+        // The peer is not part of the digest.
+        let expected = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 3000)]).unwrap();
+        assert_eq!(fee_map.digest(), expected.digest());
+        assert!(fee_map.content_eq(&expected));
+
+        // Nor is it compared.
+        assert_eq!(fee_map, expected);
+        assert_eq!(fee_map.cmp(&expected), core::cmp::Ordering::Equal);
+        assert_eq!(
+            BTreeSet::from_iter([fee_map.clone(), expected.clone()]).len(),
+            1
+        );
+
+        // Invalid updates are rejected, and leave the map untouched.
+        let other_peer = ResponderId("peer2:8443".to_string());
+        assert_eq!(
+            fee_map.update_from_peer(&other_peer, BTreeMap::from_iter([(Mob::ID, 0)])),
+            Err(Error::InvalidFee(Mob::ID, 0))
+        );
+        assert_eq!(fee_map.last_updated_by(), Some(&peer));
+        assert_eq!(fee_map.digest(), expected.digest());
+    }
+
+    #[test]
+    fn common_tokens() {
+        let fee_map1 = FeeMap::try_from_iter([
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+            (TokenId::from(3), 3000),
+        ])
+        .unwrap();
+
+        // Fully overlapping, even with different fees.
+        let fee_map2 = FeeMap::try_from_iter([
+            (Mob::ID, 200),
+            (TokenId::from(2), 2000),
+            (TokenId::from(3), 30),
+        ])
+        .unwrap();
+        assert_eq!(
+            fee_map1.common_tokens(&fee_map2),
+            BTreeSet::from_iter([Mob::ID, TokenId::from(2), TokenId::from(3)])
+        );
+
+        // Partially overlapping.
+        let fee_map3 = FeeMap::try_from_iter([
+            (Mob::ID, 100),
+            (TokenId::from(3), 3000),
+            (TokenId::from(4), 4000),
+        ])
+        .unwrap();
+        assert_eq!(
+            fee_map1.common_tokens(&fee_map3),
+            BTreeSet::from_iter([Mob::ID, TokenId::from(3)])
+        );
+        assert_eq!(
+            fee_map3.common_tokens(&fee_map1),
+            fee_map1.common_tokens(&fee_map3)
+        );
+
+        // Disjoint except for MOB.
+        let fee_map4 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(5), 5000)]).unwrap();
+        assert_eq!(
+            fee_map1.common_tokens(&fee_map4),
+            BTreeSet::from_iter([Mob::ID])
+        );
+    }
+
+    #[test]
+    fn approx_memory_size_grows_with_tokens() {
+        let mut fee_map = FeeMap::default();
+        let mut size = fee_map.approx_memory_size();
+        assert!(size > fee_map.digest().len());
+
+        for token_id in 2..10 {
+            fee_map
+                .set_fee(TokenId::from(token_id), Fee::from(1000))
+                .unwrap();
+            let new_size = fee_map.approx_memory_size();
+            assert!(new_size > size);
+            size = new_size;
+        }
+    }
+
+    #[test]
+    fn retain_keeps_mob() {
+        let mut fee_map = FeeMap::try_from_iter([
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+            (TokenId::from(3), 30),
+        ])
+        .unwrap();
+
+        fee_map.retain(|_token_id, fee| *fee >= 100).unwrap();
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap()
+        );
+    }
+
+    #[test]
+    fn retain_cannot_drop_mob() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let original = fee_map.clone();
+
+        assert_eq!(
+            fee_map.retain(|_token_id, fee| *fee > 100),
+            Err(Error::MissingFee(Mob::ID))
+        );
+        assert_eq!(fee_map, original);
+    }
+
+    #[test]
+    fn verify_digest() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let digest = fee_map.digest().to_string();
+
+        assert!(fee_map.verify_digest(&digest));
+        assert!(fee_map.verify_digest(&digest.to_uppercase()));
+        assert!(!fee_map.verify_digest(FeeMap::default().digest()));
+        assert!(!fee_map.verify_digest(&digest[1..]));
+        assert!(!fee_map.verify_digest(""));
+    }
+
+    #[test]
+    fn into_iterator_for_ref() {
+        let fee_map = FeeMap::try_from_iter([(TokenId::from(2), 2000), (Mob::ID, 100)]).unwrap();
+
+        let mut entries = Vec::new();
+        for (token_id, fee) in &fee_map {
+            entries.push((*token_id, *fee));
+        }
+        assert_eq!(entries, vec![(Mob::ID, 100), (TokenId::from(2), 2000)]);
+    }
+
+    #[test]
+    fn fee_arithmetic() {
+        let fee = Fee::from(100);
+        assert_eq!(*fee, 100);
+        assert_eq!(u64::from(fee), 100);
+        assert_eq!(fee.to_string(), "100");
+
+        assert_eq!(fee.checked_add(Fee::from(50)), Some(Fee::from(150)));
+        assert_eq!(Fee::from(u64::MAX).checked_add(fee), None);
+        assert_eq!(fee.checked_sub(Fee::from(50)), Some(Fee::from(50)));
+        assert_eq!(fee.checked_sub(Fee::from(101)), None);
+        assert_eq!(fee.checked_mul(3), Some(Fee::from(300)));
+        assert_eq!(Fee::from(u64::MAX).checked_mul(2), None);
+        assert_eq!(Fee::from(u64::MAX).saturating_add(fee), Fee::from(u64::MAX));
+        assert!(Fee::from(1) < Fee::from(2));
+    }
+
+    #[test]
+    fn default_for_tokens() {
+        let fee_map =
+            FeeMap::default_for_tokens(&[(TokenId::from(2), 2000), (TokenId::from(3), 3000)])
+                .unwrap();
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([
+                (Mob::ID, Mob::MINIMUM_FEE),
+                (TokenId::from(2), 2000),
+                (TokenId::from(3), 3000),
+            ])
+            .unwrap()
+        );
+
+        assert_eq!(FeeMap::default_for_tokens(&[]).unwrap(), FeeMap::default());
+
+        // MOB can be overridden.
+        let fee_map =
+            FeeMap::default_for_tokens(&[(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&Mob::ID),
+            Some(Fee::from(100))
+        );
+
+        // The result is validated.
+        assert_eq!(
+            FeeMap::default_for_tokens(&[(TokenId::from(2), 0)]),
+            Err(Error::InvalidFee(TokenId::from(2), 0))
+        );
+    }
+
+    #[test]
+    fn default_with_seed() {
+        let seed = [(TokenId::from(2), 2000), (TokenId::from(3), 3000)];
+        let fee_map = FeeMap::default_with_seed(&seed).unwrap();
+        assert_eq!(
+            fee_map.as_sorted_vec(),
+            vec![
+                (Mob::ID, Mob::MINIMUM_FEE),
+                (TokenId::from(2), 2000),
+                (TokenId::from(3), 3000),
+            ]
+        );
+        assert_eq!(fee_map, FeeMap::default_for_tokens(&seed).unwrap());
+
+        assert_eq!(FeeMap::default_with_seed(&[]).unwrap(), FeeMap::default());
+
+        // MOB is always included, and the seed may set its fee.
+        let fee_map = FeeMap::default_with_seed(&[(Mob::ID, 100)]).unwrap();
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&Mob::ID),
+            Some(Fee::from(100))
+        );
+
+        assert_eq!(
+            FeeMap::default_with_seed(&[(TokenId::from(2), 2000), (TokenId::from(2), 3000)]),
+            Err(Error::DuplicateToken(TokenId::from(2)))
+        );
+        assert_eq!(
+            FeeMap::default_with_seed(&[(TokenId::from(2), 0)]),
+            Err(Error::InvalidFee(TokenId::from(2), 0))
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn toml_round_trip() {
+        let mut with_policy =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        with_policy
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        let fee_maps = [
+            FeeMap::default(),
+            FeeMap::try_from_iter([
+                (TokenId::from(10), 1000),
+                (Mob::ID, 100),
+                (TokenId::from(2), 2000),
+            ])
+            .unwrap(),
+            with_policy,
+            FeeMap::with_max_fee(
+                BTreeMap::from_iter([(Mob::ID, DEFAULT_MAXIMUM_FEE + 1)]),
+                u64::MAX,
+            )
+            .unwrap(),
+        ];
+
+        for fee_map in fee_maps.iter() {
+            let toml = fee_map.to_toml().unwrap();
+            assert!(toml.starts_with("[fees]\n"), "{}", toml);
+            let recovered = FeeMap::from_toml(&toml).unwrap();
+            assert_eq!(&recovered, fee_map);
+            assert!(recovered.content_eq(fee_map));
+        }
+
+        let fee_map =
+            FeeMap::from_toml("[fees]\n0 = 100\n2 = 2000\n\n[basis_points]\n2 = 25\n").unwrap();
+        assert_eq!(
+            fee_map.fee_policy(&TokenId::from(2)),
+            Some(FeePolicy::BasisPoints(25))
+        );
+
+        let fee_map = FeeMap::from_toml("[fees]\n\"0\" = 100\n2 = 2000\n").unwrap();
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap()
+        );
+
+        // Fees which do not fit in a TOML integer cannot be written.
+        let fee_map =
+            FeeMap::with_max_fee(BTreeMap::from_iter([(Mob::ID, u64::MAX)]), u64::MAX).unwrap();
+        assert!(matches!(fee_map.to_toml(), Err(Error::Serialize(_))));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn from_toml_rejects_invalid_fee_maps() {
+        assert_eq!(
+            FeeMap::from_toml("[fees]\n2 = 2000\n"),
+            Err(Error::MissingFee(Mob::ID))
+        );
+        assert!(matches!(
+            FeeMap::from_toml("[fees]\nEUR = 2000\n"),
+            Err(Error::Parse(_))
+        ));
+        assert!(matches!(
+            FeeMap::from_toml("0 = 2000\n"),
+            Err(Error::Parse(_))
+        ));
+
+        // Basis points need a minimum fee for the same token.
+        assert_eq!(
+            FeeMap::from_toml("[fees]\n0 = 100\n\n[basis_points]\n2 = 25\n"),
+            Err(Error::MissingFee(TokenId::from(2)))
+        );
+    }
+
+    #[test]
+    fn fee_map_from_const_fee_entries() {
+        use mc_transaction_core::const_fee_entries;
+
+        let fee_map = FeeMap::try_from_iter(const_fee_entries!(Mob)).unwrap();
+        assert_eq!(fee_map, FeeMap::default());
+    }
+
+    #[test]
+    fn map_fees_doubles_fees() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        let doubled = fee_map.map_fees(|_token_id, fee| fee * 2).unwrap();
+        assert_eq!(
+            doubled,
+            FeeMap::try_from_iter([(Mob::ID, 200), (TokenId::from(2), 4000)]).unwrap()
+        );
+
+        // The original is untouched.
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&Mob::ID),
+            Some(Fee::from(100))
+        );
+    }
+
+    #[test]
+    fn map_fees_rejects_invalid_results() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        assert_eq!(
+            fee_map.map_fees(|token_id, fee| if token_id == Mob::ID { fee } else { 0 }),
+            Err(Error::InvalidFee(TokenId::from(2), 0))
+        );
+        assert_eq!(
+            fee_map.map_fees(|_token_id, _fee| DEFAULT_MAXIMUM_FEE + 1),
+            Err(Error::FeeTooLarge(Mob::ID, DEFAULT_MAXIMUM_FEE + 1))
+        );
+    }
+
+    /// No-op updates do not recompute the digest.
+    #[test]
+    fn update_or_default_skips_no_op_updates() {
+        let minimum_fees = BTreeMap::from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]);
+        let mut fee_map = FeeMap::try_from(minimum_fees.clone()).unwrap();
+        let digest = fee_map.digest().to_string();
+        let digest_ptr = fee_map.digest().as_ptr();
+
+        fee_map
+            .update_or_default(Some(minimum_fees.clone()))
+            .unwrap();
+        assert_eq!(fee_map.digest(), digest);
+        assert_eq!(fee_map.digest().as_ptr(), digest_ptr);
+
+        // A real update still changes the digest.
+        fee_map.update_or_default(None).unwrap();
+        assert_eq!(fee_map, FeeMap::default());
+        let digest_ptr = fee_map.digest().as_ptr();
+
+        // Resetting an already-default map is a no-op.
+        fee_map.update_or_default(None).unwrap();
+        assert_eq!(fee_map, FeeMap::default());
+        assert_eq!(fee_map.digest().as_ptr(), digest_ptr);
+
+        // Updating to the same fees still resets fee policies.
+        let mut fee_map = FeeMap::try_from(minimum_fees.clone()).unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+        fee_map.update_or_default(Some(minimum_fees)).unwrap();
+        assert_eq!(
+            fee_map.fee_policy(&TokenId::from(2)),
+            Some(FeePolicy::Flat(2000))
+        );
+        assert_eq!(fee_map.digest(), digest);
+    }
+
+    #[test]
+    fn as_sorted_vec_is_sorted() {
+        let fee_map = FeeMap::try_from_iter([
+            (TokenId::from(10), 1000),
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+        ])
+        .unwrap();
+
+        let entries = fee_map.as_sorted_vec();
+        assert_eq!(
+            entries,
+            vec![
+                (Mob::ID, 100),
+                (TokenId::from(2), 2000),
+                (TokenId::from(10), 1000)
+            ]
+        );
+        assert_eq!(
+            entries
+                .iter()
+                .map(|(token_id, _fee)| *token_id)
+                .collect::<Vec<_>>(),
+            fee_map.token_ids().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn new_with_mob_fee() {
+        let fee_map = FeeMap::new_with_mob_fee(1).unwrap();
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&Mob::ID),
+            Some(Fee::from(1))
+        );
+        assert_eq!(fee_map.len(), 1);
+        assert!(!fee_map.mob_fee_is_default());
+
+        assert_eq!(
+            FeeMap::new_with_mob_fee(Mob::MINIMUM_FEE).unwrap(),
+            FeeMap::default()
+        );
+
+        assert_eq!(
+            FeeMap::new_with_mob_fee(0),
+            Err(Error::InvalidFee(Mob::ID, 0))
+        );
+    }
+
+    #[test]
+    fn is_superset_of() {
+        let small = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let large = FeeMap::try_from_iter([
+            (Mob::ID, 200),
+            (TokenId::from(2), 20),
+            (TokenId::from(3), 3000),
+        ])
+        .unwrap();
+
+        assert!(large.is_superset_of(&small));
+        assert!(!small.is_superset_of(&large));
+
+        // Every map is a superset of itself.
+        assert!(small.is_superset_of(&small));
+        assert!(small.is_superset_of(&FeeMap::default()));
+
+        // A map missing a token is not a superset.
+        let other = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(4), 4000)]).unwrap();
+        assert!(!large.is_superset_of(&other));
+    }
+
+    /// Making the digest fallible does not change the digest of valid maps,
+    /// however they are constructed.
+    #[test]
+    fn digest_is_unchanged_for_valid_maps() {
+        let map = BTreeMap::from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]);
+        let expected = calc_digest_bytes(&map, &BTreeMap::new(), FeeMap::DIGEST_VERSION).unwrap();
+
+        let from_map = FeeMap::try_from(map.clone()).unwrap();
+        assert_eq!(from_map.digest_bytes(), expected);
+        assert_eq!(from_map.digest(), hex::encode(expected));
+
+        let mut mutated = FeeMap::default();
+        mutated.set_fee(Mob::ID, Fee::from(100)).unwrap();
+        mutated.set_fee(TokenId::from(2), Fee::from(2000)).unwrap();
+        mutated.set_fee(TokenId::from(3), Fee::from(3000)).unwrap();
+        mutated.remove_fee(TokenId::from(3)).unwrap();
+        assert_eq!(mutated.digest_bytes(), expected);
+
+        let mut updated = FeeMap::default();
+        updated.update_or_default(Some(map)).unwrap();
+        assert_eq!(updated.digest_bytes(), expected);
+    }
+
+    /// Generate a valid set of fees, always including MOB, together with the
+    /// same entries in two arbitrary insertion orders.
+    fn arbitrary_fee_entries() -> impl Strategy<Value = (Vec<(TokenId, u64)>, Vec<(TokenId, u64)>)>
+    {
+        prop::collection::btree_map(any::<u32>(), 1..=DEFAULT_MAXIMUM_FEE, 0..16)
+            .prop_map(|fees| {
+                let mut entries: Vec<_> = fees
+                    .into_iter()
+                    .map(|(token_id, fee)| (TokenId::from(token_id), fee))
+                    .filter(|(token_id, _fee)| *token_id != Mob::ID)
+                    .collect();
+                entries.push((Mob::ID, Mob::MINIMUM_FEE));
+                entries
+            })
+            .prop_flat_map(|entries| {
+                (
+                    Just(entries.clone()).prop_shuffle(),
+                    Just(entries).prop_shuffle(),
+                )
+            })
+    }
+
+    proptest! {
+        #[test]
+        /// The digest depends only on the fees, not the order they were added in.
+        fn digest_is_independent_of_insertion_order(
+            (entries1, entries2) in arbitrary_fee_entries()
+        ) {
+            let fee_map1 = FeeMap::try_from_iter_strict(entries1.clone()).unwrap();
+            let fee_map2 = FeeMap::try_from_iter_strict(entries2.clone()).unwrap();
+            prop_assert_eq!(fee_map1.digest(), fee_map2.digest());
+
+            let mut incremental = FeeMap::default();
+            for (token_id, fee) in entries2.iter().rev() {
+                incremental.set_fee(*token_id, Fee::from(*fee)).unwrap();
+            }
+            prop_assert_eq!(fee_map1.digest(), incremental.digest());
+
+            // Recomputing the digest from scratch gives the same answer.
+            let map = BTreeMap::from_iter(entries1);
+            prop_assert_eq!(
+                fee_map1.digest_bytes(),
+                calc_digest_bytes(&map, &BTreeMap::new(), FeeMap::DIGEST_VERSION).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn filtered_digest() {
+        let fee_map = FeeMap::try_from_iter([
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+            (TokenId::from(3), 3000),
+        ])
+        .unwrap();
+        let subset_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        // Only the selected entries contribute to the digest.
+        let subset = BTreeSet::from_iter([Mob::ID, TokenId::from(2)]);
+        assert_eq!(
+            fee_map.filtered_digest(&subset),
+            subset_map.filtered_digest(&subset)
+        );
+        assert_ne!(
+            fee_map.filtered_digest(&subset),
+            fee_map.filtered_digest(&BTreeSet::from_iter([Mob::ID]))
+        );
+        // Unknown tokens are ignored.
+        assert_eq!(
+            fee_map.filtered_digest(&subset),
+            fee_map.filtered_digest(&BTreeSet::from_iter([
+                Mob::ID,
+                TokenId::from(2),
+                TokenId::from(4)
+            ]))
+        );
+
+        // Filtering by every token still differs from the full digest.
+        let all_tokens = fee_map.token_ids().collect::<BTreeSet<_>>();
+        assert_ne!(fee_map.filtered_digest(&all_tokens), fee_map.digest());
+
+        // An empty filter gives the same digest for every fee map.
+        assert_eq!(
+            fee_map.filtered_digest(&BTreeSet::new()),
+            FeeMap::default().filtered_digest(&BTreeSet::new())
+        );
+        assert_ne!(
+            fee_map.filtered_digest(&BTreeSet::new()),
+            fee_map.filtered_digest(&subset)
+        );
+    }
+
+    #[test]
+    fn split_fee_digest_recovers_base_responder_id() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let base = ResponderId("node1.test.mobilecoin.com:443".to_string());
+        let responder_id = fee_map.responder_id(&base);
+
+        assert_eq!(
+            split_fee_digest(&responder_id),
+            Some((base.clone(), fee_map.digest().to_string()))
+        );
+
+        // Only the last `-` separates the digest.
+        let dashed = ResponderId("node-1.test:443".to_string());
+        assert_eq!(
+            split_fee_digest(&fee_map.responder_id(&dashed)),
+            Some((dashed.clone(), fee_map.digest().to_string()))
+        );
+
+        // Ids without a digest suffix are not split.
+        assert_eq!(split_fee_digest(&base), None);
+        assert_eq!(split_fee_digest(&dashed), None);
+        assert_eq!(
+            split_fee_digest(&ResponderId(format!("{}-{}", base, "z".repeat(64)))),
+            None
+        );
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let mut fee_map = FeeMap::try_from_iter([
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+            (TokenId::from(u32::MAX), DEFAULT_MAXIMUM_FEE),
+        ])
+        .unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        let bytes = fee_map.to_bytes();
+        assert_eq!(bytes.len(), 4 + 3 * 12 + 4 + 6);
+
+        let decoded = FeeMap::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, fee_map);
+        assert_eq!(decoded.digest(), fee_map.digest());
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        let default = FeeMap::default();
+        assert_eq!(FeeMap::from_bytes(&default.to_bytes()).unwrap(), default);
+    }
+
+    #[test]
+    fn from_bytes_rejects_invalid_input() {
+        let bytes = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)])
+            .unwrap()
+            .to_bytes();
+
+        // Every strict prefix is truncated.
+        for len in 0..bytes.len() {
+            assert_eq!(
+                FeeMap::from_bytes(&bytes[..len]),
+                Err(Error::Parse("unexpected end of fee map bytes".to_string()))
+            );
+        }
+
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert_eq!(
+            FeeMap::from_bytes(&trailing),
+            Err(Error::Parse("trailing bytes after fee map".to_string()))
+        );
+
+        // Entries out of order.
+        let mut unsorted = Vec::new();
+        unsorted.extend_from_slice(&2u32.to_le_bytes());
+        unsorted.extend_from_slice(&2u32.to_le_bytes());
+        unsorted.extend_from_slice(&2000u64.to_le_bytes());
+        unsorted.extend_from_slice(&0u32.to_le_bytes());
+        unsorted.extend_from_slice(&100u64.to_le_bytes());
+        unsorted.extend_from_slice(&0u32.to_le_bytes());
+        assert!(matches!(
+            FeeMap::from_bytes(&unsorted),
+            Err(Error::Parse(_))
+        ));
+
+        // Decoded fees are validated.
+        let mut missing_mob = Vec::new();
+        missing_mob.extend_from_slice(&1u32.to_le_bytes());
+        missing_mob.extend_from_slice(&2u32.to_le_bytes());
+        missing_mob.extend_from_slice(&2000u64.to_le_bytes());
+        missing_mob.extend_from_slice(&0u32.to_le_bytes());
+        assert_eq!(
+            FeeMap::from_bytes(&missing_mob),
+            Err(Error::MissingFee(Mob::ID))
+        );
+    }
+
+    #[test]
+    fn is_valid_map_with_floors() {
+        let floors = BTreeMap::from_iter([(TokenId::from(2), 1000)]);
+
+        // At the floor.
+        let at_floor =
+            BTreeMap::from_iter([(Mob::ID, DEFAULT_MOB_FEE_FLOOR), (TokenId::from(2), 1000)]);
+        assert_eq!(
+            FeeMap::is_valid_map_with_floors(&at_floor, floors.clone()),
+            Ok(())
+        );
+
+        // Below the floor.
+        let below_floor =
+            BTreeMap::from_iter([(Mob::ID, DEFAULT_MOB_FEE_FLOOR), (TokenId::from(2), 999)]);
+        assert_eq!(
+            FeeMap::is_valid_map_with_floors(&below_floor, floors.clone()),
+            Err(Error::FeeBelowFloor(TokenId::from(2), 999, 1000))
+        );
+
+        // MOB has a floor by default, which can be overridden.
+        let low_mob = BTreeMap::from_iter([(Mob::ID, DEFAULT_MOB_FEE_FLOOR - 1)]);
+        assert_eq!(
+            FeeMap::is_valid_map_with_floors(&low_mob, []),
+            Err(Error::FeeBelowFloor(
+                Mob::ID,
+                DEFAULT_MOB_FEE_FLOOR - 1,
+                DEFAULT_MOB_FEE_FLOOR
+            ))
+        );
+        assert_eq!(
+            FeeMap::is_valid_map_with_floors(&low_mob, [(Mob::ID, 1)]),
+            Ok(())
+        );
+
+        // Tokens without a floor only need a nonzero fee.
+        let no_floor =
+            BTreeMap::from_iter([(Mob::ID, DEFAULT_MOB_FEE_FLOOR), (TokenId::from(3), 1)]);
+        assert_eq!(
+            FeeMap::is_valid_map_with_floors(&no_floor, floors.clone()),
+            Ok(())
+        );
+        assert_eq!(
+            FeeMap::is_valid_map_with_floors(
+                &BTreeMap::from_iter([(Mob::ID, DEFAULT_MOB_FEE_FLOOR), (TokenId::from(3), 0)]),
+                floors
+            ),
+            Err(Error::InvalidFee(TokenId::from(3), 0))
+        );
+    }
+
+    #[test]
+    fn validate_all_collects_every_error() {
+        let minimum_fees = BTreeMap::from_iter([
+            (Mob::ID, DEFAULT_MAXIMUM_FEE + 1),
+            (TokenId::from(2), 0),
+            (TokenId::from(4), 0),
+            (TokenId::from(5), 500),
+        ]);
+        let errors = FeeMap::validate_all(&minimum_fees).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                Error::InvalidFee(TokenId::from(2), 0),
+                Error::InvalidFee(TokenId::from(4), 0),
+                Error::FeeTooLarge(Mob::ID, DEFAULT_MAXIMUM_FEE + 1),
+            ]
+        );
+        assert_eq!(FeeMap::is_valid_map(&minimum_fees), Err(errors[0].clone()));
+
+        let missing_mob = BTreeMap::from_iter([(TokenId::from(2), 0)]);
+        assert_eq!(
+            FeeMap::validate_all(&missing_mob),
+            Err(vec![
+                Error::InvalidFee(TokenId::from(2), 0),
+                Error::MissingFee(Mob::ID),
+            ])
+        );
+
+        let valid = BTreeMap::from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]);
+        assert_eq!(FeeMap::validate_all(&valid), Ok(()));
+        assert_eq!(
+            FeeMap::validate_all(&BTreeMap::new()),
+            Err(vec![Error::MissingFee(Mob::ID)])
+        );
+    }
+
+    #[test]
+    fn validate_all_with_max_fee_respects_max_fee() {
+        let minimum_fees = BTreeMap::from_iter([
+            (Mob::ID, 1001),
+            (TokenId::from(2), 0),
+            (TokenId::from(3), 1000),
+            (TokenId::from(4), u64::MAX),
+        ]);
+        let errors = FeeMap::validate_all_with_max_fee(&minimum_fees, 1000).unwrap_err();
+        assert_eq!(
+            errors,
+            vec![
+                Error::InvalidFee(TokenId::from(2), 0),
+                Error::FeeTooLarge(Mob::ID, 1001),
+                Error::FeeTooLarge(TokenId::from(4), u64::MAX),
+            ]
+        );
+        assert_eq!(
+            FeeMap::is_valid_map_with_max_fee(&minimum_fees, 1000),
+            Err(errors[0].clone())
+        );
+
+        // A single ceiling bounds every token, and can exceed the defaults.
+        let minimum_fees = BTreeMap::from_iter([(Mob::ID, 100), (TokenId::from(2), u64::MAX)]);
+        assert_eq!(
+            FeeMap::validate_all(&minimum_fees),
+            Err(vec![Error::FeeTooLarge(TokenId::from(2), u64::MAX)])
+        );
+        assert_eq!(
+            FeeMap::validate_all_with_max_fee(&minimum_fees, 1000),
+            Err(vec![Error::FeeTooLarge(TokenId::from(2), u64::MAX)])
+        );
+        assert_eq!(
+            FeeMap::validate_all_with_max_fee(&minimum_fees, u64::MAX),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn is_valid_map_with_token_minimum_fee_floors() {
+        use mc_transaction_core::const_fee_entries;
+
+        let at_minimum = BTreeMap::from_iter([(Mob::ID, Mob::MINIMUM_FEE)]);
+        assert_eq!(
+            FeeMap::is_valid_map_with_floors(&at_minimum, [fee_entry::<Mob>()]),
+            Ok(())
+        );
+
+        // MOB's minimum fee is enforced instead of the default floor.
+        let below_minimum = BTreeMap::from_iter([(Mob::ID, Mob::MINIMUM_FEE - 1)]);
+        assert_eq!(FeeMap::is_valid_map_with_floors(&below_minimum, []), Ok(()));
+        assert_eq!(
+            FeeMap::is_valid_map_with_floors(&below_minimum, const_fee_entries!(Mob)),
+            Err(Error::FeeBelowFloor(
+                Mob::ID,
+                Mob::MINIMUM_FEE - 1,
+                Mob::MINIMUM_FEE
+            ))
+        );
+    }
+
+    #[test]
+    fn reset_token_to_default() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        fee_map.reset_mob_to_default().unwrap();
+        assert!(fee_map.mob_fee_is_default());
+
+        fee_map
+            .reset_token_to_default(&TokenId::from(2), 1024)
+            .unwrap();
+        assert_eq!(
+            fee_map.fee_policy(&TokenId::from(2)),
+            Some(FeePolicy::Flat(1024))
+        );
+        assert_eq!(
+            fee_map,
+            FeeMap::try_from_iter([(Mob::ID, Mob::MINIMUM_FEE), (TokenId::from(2), 1024)]).unwrap()
+        );
+
+        // The default must be a valid fee.
+        assert_eq!(
+            fee_map.reset_token_to_default(&TokenId::from(2), 0),
+            Err(Error::InvalidFee(TokenId::from(2), 0))
+        );
+        assert_eq!(
+            fee_map.get_minimum_fee_for_token(&TokenId::from(2)),
+            Some(Fee(1024))
+        );
+    }
+
+    #[test]
+    fn fee_maps_are_ordered_by_digest() {
+        let fee_map1 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let fee_map2 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 3000)]).unwrap();
+        let fee_map3 = FeeMap::default();
+
+        let set = BTreeSet::from_iter([
+            fee_map1.clone(),
+            fee_map2.clone(),
+            fee_map3.clone(),
+            FeeMap::try_from_iter([(TokenId::from(2), 2000), (Mob::ID, 100)]).unwrap(),
+            fee_map3.clone(),
+        ]);
+        assert_eq!(set.len(), 3);
+        assert!(set.contains(&fee_map1));
+        assert!(set.contains(&fee_map2));
+        assert!(set.contains(&fee_map3));
+
+        // Fee maps which differ only in their fee policies are distinct.
+        let mut with_policy = fee_map1.clone();
+        with_policy
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+        assert_ne!(with_policy.digest(), fee_map1.digest());
+        assert_ne!(with_policy.cmp(&fee_map1), core::cmp::Ordering::Equal);
+
+        // Iteration follows the digest.
+        let digests = set
+            .iter()
+            .map(|fee_map| fee_map.digest_bytes())
+            .collect::<Vec<_>>();
+        let mut sorted_digests = digests.clone();
+        sorted_digests.sort_unstable();
+        assert_eq!(digests, sorted_digests);
+    }
+
+    #[test]
+    fn update_or_default_returning_returns_previous_map() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let original = fee_map.clone();
+
+        let new_fees = BTreeMap::from_iter([(Mob::ID, 200)]);
+        let previous = fee_map
+            .update_or_default_returning(Some(new_fees.clone()))
+            .unwrap();
+        assert_eq!(previous, original);
+        assert_eq!(fee_map, FeeMap::try_from(new_fees).unwrap());
+
+        let previous = fee_map.update_or_default_returning(None).unwrap();
+        assert_eq!(previous, FeeMap::try_from_iter([(Mob::ID, 200)]).unwrap());
+        assert_eq!(fee_map, FeeMap::default());
+
+        // Failed updates leave the map unchanged.
+        assert_eq!(
+            fee_map
+                .update_or_default_returning(Some(BTreeMap::from_iter([(TokenId::from(2), 2000)]))),
+            Err(Error::MissingFee(Mob::ID))
+        );
+        assert_eq!(fee_map, FeeMap::default());
+    }
+
+    #[test]
+    fn fee_status() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        assert_eq!(
+            fee_map.fee_status(&Mob::ID),
+            FeeStatus::Configured(Fee::from(100))
+        );
+        assert_eq!(
+            fee_map.fee_status(&TokenId::from(2)),
+            FeeStatus::Configured(Fee::from(2000))
+        );
+        assert_eq!(
+            fee_map.fee_status(&TokenId::from(3)),
+            FeeStatus::Unsupported
+        );
+    }
+
+    #[test]
+    fn get_fees() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        assert_eq!(
+            fee_map.get_fees(&[TokenId::from(2), Mob::ID, TokenId::from(2)]),
+            Ok(vec![Fee::from(2000), Fee::from(100), Fee::from(2000)])
+        );
+        assert_eq!(fee_map.get_fees(&[]), Ok(vec![]));
+
+        assert_eq!(
+            fee_map.get_fees(&[Mob::ID, TokenId::from(3), TokenId::from(4)]),
+            Err(Error::MissingFee(TokenId::from(3)))
+        );
+    }
+
+    #[test]
+    fn try_from_validated_matches_try_from() {
+        let map = BTreeMap::from_iter(
+            (0..1000).map(|token_id| (TokenId::from(token_id), 1000 + token_id as u64)),
+        );
+        FeeMap::is_valid_map(&map).unwrap();
+
+        let validated = FeeMap::try_from_validated(map.clone());
+        let checked = FeeMap::try_from(map).unwrap();
+        assert_eq!(validated, checked);
+        assert_eq!(validated.digest(), checked.digest());
+    }
+
+    /// Debug builds catch invalid maps passed to the trusted constructor.
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "fee map passed to try_from_validated is not valid")]
+    fn try_from_validated_checks_in_debug_builds() {
+        FeeMap::try_from_validated(BTreeMap::from_iter([(TokenId::from(2), 2000)]));
+    }
+
+    #[test]
+    fn display() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(TokenId::from(2), 2000), (Mob::ID, 400_000_000)]).unwrap();
+        assert_eq!(format!("{}", fee_map), "MOB=400000000, 2=2000");
+
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+        assert_eq!(format!("{}", fee_map), "MOB=400000000, 2=2000 (25 bps)");
+
+        assert_eq!(
+            FeeMap::default().to_string(),
+            format!("MOB={}", Mob::MINIMUM_FEE)
+        );
+    }
+
+    #[test]
+    fn epoch_fee_map_lookups() {
+        let fee_map1 = FeeMap::try_from_iter([(Mob::ID, 100)]).unwrap();
+        let fee_map2 = FeeMap::try_from_iter([(Mob::ID, 200)]).unwrap();
+        let fee_map3 = FeeMap::try_from_iter([(Mob::ID, 300)]).unwrap();
+        let mut epochs = EpochFeeMap::new(BTreeMap::from_iter([
+            (10, fee_map1.clone()),
+            (20, fee_map2.clone()),
+        ]));
+
+        // Before the first epoch.
+        assert_eq!(epochs.fee_map_for_block(0), Err(Error::NoFeeMapForBlock(0)));
+        assert_eq!(epochs.fee_map_for_block(9), Err(Error::NoFeeMapForBlock(9)));
+
+        // Exactly at an epoch boundary.
+        assert_eq!(epochs.fee_map_for_block(10), Ok(&fee_map1));
+        assert_eq!(epochs.fee_map_for_block(20), Ok(&fee_map2));
+
+        // Between epochs, and after the last one.
+        assert_eq!(epochs.fee_map_for_block(15), Ok(&fee_map1));
+        assert_eq!(epochs.fee_map_for_block(19), Ok(&fee_map1));
+        assert_eq!(epochs.fee_map_for_block(u64::MAX), Ok(&fee_map2));
+
+        // Scheduling a later epoch.
+        assert_eq!(epochs.insert_epoch(30, fee_map3.clone()), None);
+        assert_eq!(epochs.fee_map_for_block(25), Ok(&fee_map2));
+        assert_eq!(epochs.fee_map_for_block(30), Ok(&fee_map3));
+        assert_eq!(epochs.insert_epoch(30, fee_map1.clone()), Some(fee_map3));
+
+        assert_eq!(
+            EpochFeeMap::default().fee_map_for_block(0),
+            Err(Error::NoFeeMapForBlock(0))
+        );
+    }
+
+    #[test]
+    fn fee_map_controller_enforces_cooldown() {
+        let mut controller = FeeMapController::new(FeeMap::default());
+        let fees1 = BTreeMap::from_iter([(Mob::ID, 100)]);
+        let fees2 = BTreeMap::from_iter([(Mob::ID, 200)]);
+
+        // The first update is always allowed.
+        assert!(controller.can_update(1000, 60));
+        controller
+            .update_or_default(Some(fees1.clone()), 1000, 60)
+            .unwrap();
+        assert_eq!(
+            controller.fee_map(),
+            &FeeMap::try_from(fees1.clone()).unwrap()
+        );
+        assert_eq!(controller.last_updated_at(), Some(1000));
+
+        // Too soon.
+        assert!(!controller.can_update(1059, 60));
+        assert_eq!(
+            controller.update_or_default(Some(fees2.clone()), 1059, 60),
+            Err(Error::UpdateTooSoon(1000, 1060))
+        );
+        assert_eq!(controller.fee_map(), &FeeMap::try_from(fees1).unwrap());
+        assert_eq!(controller.last_updated_at(), Some(1000));
+
+        // Allowed once the interval has passed.
+        assert!(controller.can_update(1060, 60));
+        controller
+            .update_or_default(Some(fees2.clone()), 1060, 60)
+            .unwrap();
+        assert_eq!(
+            controller.fee_map(),
+            &FeeMap::try_from(fees2.clone()).unwrap()
+        );
+        assert_eq!(controller.last_updated_at(), Some(1060));
+
+        // Invalid updates do not reset the cooldown.
+        assert_eq!(
+            controller.update_or_default(Some(BTreeMap::new()), 2000, 60),
+            Err(Error::MissingFee(Mob::ID))
+        );
+        assert_eq!(controller.last_updated_at(), Some(1060));
+
+        // Neither do updates which change nothing.
+        controller
+            .update_or_default(Some(fees2.clone()), 2000, 60)
+            .unwrap();
+        assert_eq!(controller.fee_map(), &FeeMap::try_from(fees2).unwrap());
+        assert_eq!(controller.last_updated_at(), Some(1060));
+        assert!(controller.can_update(2001, 60));
+    }
+
+    #[test]
+    fn fee_entry_for_token() {
+        assert_eq!(fee_entry::<Mob>(), (TokenId::MOB, Mob::MINIMUM_FEE));
+        assert_eq!(
+            FeeMap::default_map(),
+            BTreeMap::from_iter([fee_entry::<Mob>()])
+        );
+    }
+
+    #[test]
+    fn max_fee_for_token_is_keyed_by_token() {
+        assert_eq!(max_fee_for_token::<Mob>(), DEFAULT_MAXIMUM_FEE);
+        assert_eq!(FeeMap::default_max_fee(&Mob::ID), DEFAULT_MAXIMUM_FEE);
+        assert_eq!(
+            FeeMap::default_max_fee(&TokenId::from(2)),
+            DEFAULT_MAXIMUM_FEE
+        );
+
+        struct SixDecimals;
+        impl Token for SixDecimals {
+            const ID: TokenId = TokenId::EUSD;
+            const MINIMUM_FEE: u64 = 1024;
+            const SYMBOL: &'static str = "SIX";
+            const DECIMALS: u8 = 6;
+        }
+        assert_eq!(max_fee_for_token::<SixDecimals>(), 1_000_000_000);
+
+        struct ScarceToken;
+        impl Token for ScarceToken {
+            const ID: TokenId = TokenId::EUSD;
+            const MINIMUM_FEE: u64 = 1;
+            const SYMBOL: &'static str = "SCARCE";
+            const DECIMALS: u8 = 2;
+            const MAX_SUPPLY: Option<u64> = Some(10);
+        }
+        assert_eq!(max_fee_for_token::<ScarceToken>(), 1_000);
+
+        struct WideToken;
+        impl Token for WideToken {
+            const ID: TokenId = TokenId::EUSD;
+            const MINIMUM_FEE: u64 = 1;
+            const SYMBOL: &'static str = "WIDE";
+            const DECIMALS: u8 = 18;
+        }
+        assert_eq!(max_fee_for_token::<WideToken>(), u64::MAX);
+    }
+
+    #[test]
+    fn clamp_fees() {
+        let mut fee_map = FeeMap::try_from_iter([
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+            (TokenId::from(3), 30_000),
+        ])
+        .unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(3), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        let clamped = fee_map.clamp_fees(1000, 10_000).unwrap();
+        // Below the band.
+        assert_eq!(clamped.get_minimum_fee_for_token(&Mob::ID), Some(Fee(1000)));
+        // Within the band.
+        assert_eq!(
+            clamped.get_minimum_fee_for_token(&TokenId::from(2)),
+            Some(Fee(2000))
+        );
+        // Above the band.
+        assert_eq!(
+            clamped.get_minimum_fee_for_token(&TokenId::from(3)),
+            Some(Fee(10_000))
+        );
+        assert_eq!(
+            clamped.fee_policy(&TokenId::from(3)),
+            Some(FeePolicy::BasisPoints(25))
+        );
+        assert_ne!(clamped.digest(), fee_map.digest());
+
+        // A band that already contains every fee changes nothing.
+        assert_eq!(fee_map.clamp_fees(1, DEFAULT_MAXIMUM_FEE).unwrap(), fee_map);
+
+        assert_eq!(
+            fee_map.clamp_fees(0, 10_000),
+            Err(Error::InvalidFeeBand(0, 10_000))
+        );
+        assert_eq!(
+            fee_map.clamp_fees(10_001, 10_000),
+            Err(Error::InvalidFeeBand(10_001, 10_000))
+        );
+    }
+
+    #[test]
+    fn default_digester_matches_digest_bytes() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        assert_eq!(
+            fee_map.digest_with(&MerlinFeeMapDigester::default()),
+            fee_map.digest_bytes()
+        );
+
+        fee_map.recompute_digest(DigestVersion::V2).unwrap();
+        let digester = MerlinFeeMapDigester {
+            version: DigestVersion::V2,
+        };
+        assert_eq!(fee_map.digest_with(&digester), fee_map.digest_bytes());
+        assert_ne!(
+            fee_map.digest_with(&digester),
+            fee_map.digest_with(&MerlinFeeMapDigester::default())
+        );
+    }
+
+    /// Sums the fees into the first 16 bytes, for testing only.
+    struct SumDigester;
+    impl FeeMapDigester for SumDigester {
+        fn digest(&self, map: &BTreeMap<TokenId, u64>) -> [u8; 32] {
+            let sum = map.values().map(|fee| *fee as u128).sum::<u128>();
+            let mut result = [0u8; 32];
+            result[..16].copy_from_slice(&sum.to_le_bytes());
+            result
+        }
+    }
+
+    #[test]
+    fn alternate_digester() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let digest = fee_map.digest_with(&SumDigester);
+        assert_eq!(digest[..16], 2100u128.to_le_bytes());
+        assert_ne!(digest, fee_map.digest_bytes());
+        assert_ne!(
+            digest,
+            fee_map.digest_with(&MerlinFeeMapDigester::default())
+        );
+    }
+
+    /// A custom digester is used for the cached digest and responder id, and
+    /// kept across updates until a Merlin digest is selected again.
+    #[test]
+    fn custom_digester_is_used_for_cached_digest() {
+        let base = ResponderId("node1.test.mobilecoin.com:443".to_string());
+        let map = BTreeMap::from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]);
+        let mut fee_map = FeeMap::with_digester(map.clone(), SumDigester).unwrap();
+        fee_map.assert_invariants();
+        assert_eq!(fee_map.digest_bytes(), fee_map.digest_with(&SumDigester));
+        assert_eq!(fee_map.digest_bytes()[..16], 2100u128.to_le_bytes());
+        assert_eq!(
+            fee_map.responder_id(&base),
+            ResponderId(format!("{}-{}", base, hex::encode(fee_map.digest_bytes())))
+        );
+
+        // Updates keep using the digester, including through a clone.
+        fee_map.set_fee(TokenId::from(3), Fee::from(3000)).unwrap();
+        assert_eq!(fee_map.digest_bytes()[..16], 5100u128.to_le_bytes());
+        let mut clone = fee_map.clone();
+        clone.edit().set(TokenId::from(3), 4000).unwrap();
+        assert_eq!(clone.digest_bytes()[..16], 6100u128.to_le_bytes());
+        clone.assert_invariants();
+
+        // Switching to a Merlin digest drops the digester.
+        fee_map.remove_fee(TokenId::from(3)).unwrap();
+        fee_map.recompute_digest(FeeMap::DIGEST_VERSION).unwrap();
+        assert_eq!(fee_map, FeeMap::try_from(map.clone()).unwrap());
+        fee_map.set_fee(TokenId::from(3), Fee::from(3000)).unwrap();
+        fee_map.assert_invariants();
+
+        // The map is still validated.
+        assert_eq!(
+            FeeMap::with_digester(BTreeMap::from_iter([(TokenId::from(2), 2000)]), SumDigester),
+            Err(Error::MissingFee(Mob::ID))
+        );
+    }
+
+    #[test]
+    fn bump_all_fees_by_bps() {
+        let mut fee_map = FeeMap::try_from_iter([
+            (Mob::ID, 400_000_000),
+            (TokenId::from(2), 2000),
+            (TokenId::from(3), 15),
+        ])
+        .unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        // A 10% bump, rounding down.
+        let bumped = fee_map.bump_all_fees_by_bps(1000).unwrap();
+        assert_eq!(
+            bumped.as_sorted_vec(),
+            vec![
+                (Mob::ID, 440_000_000),
+                (TokenId::from(2), 2200),
+                (TokenId::from(3), 16),
+            ]
+        );
+        assert_eq!(
+            bumped.fee_policy(&TokenId::from(2)),
+            Some(FeePolicy::BasisPoints(25))
+        );
+        assert_ne!(bumped.digest(), fee_map.digest());
+
+        assert_eq!(fee_map.bump_all_fees_by_bps(0).unwrap(), fee_map);
+
+        // The bumped fees are validated.
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, DEFAULT_MAXIMUM_FEE)]).unwrap();
+        assert_eq!(
+            fee_map.bump_all_fees_by_bps(1),
+            Err(Error::FeeTooLarge(
+                Mob::ID,
+                DEFAULT_MAXIMUM_FEE / 10_000 * 10_001
+            ))
+        );
+
+        let fee_map =
+            FeeMap::with_max_fee(BTreeMap::from_iter([(Mob::ID, u64::MAX)]), u64::MAX).unwrap();
+        assert_eq!(
+            fee_map.bump_all_fees_by_bps(1),
+            Err(Error::Overflow(Mob::ID, u64::MAX))
+        );
+    }
+
+    #[test]
+    fn recompute_digest_switches_versions() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let base = ResponderId("node1.test.mobilecoin.com:443".to_string());
+        assert_eq!(fee_map.digest_version(), FeeMap::DIGEST_VERSION);
+        let original_digest = fee_map.digest().to_string();
+        let original_responder_id = fee_map.responder_id(&base);
+
+        fee_map.recompute_digest(DigestVersion::V2).unwrap();
+        assert_eq!(fee_map.digest_version(), DigestVersion::V2);
+        assert_ne!(fee_map.digest(), original_digest);
+        assert_eq!(
+            fee_map.digest(),
+            fee_map.digest_with_version(DigestVersion::V2).unwrap()
+        );
+        assert_ne!(fee_map.responder_id(&base), original_responder_id);
+
+        // Updates keep using the negotiated version.
+        fee_map.set_fee(TokenId::from(3), Fee::from(3000)).unwrap();
+        assert_eq!(
+            fee_map.digest(),
+            fee_map.digest_with_version(DigestVersion::V2).unwrap()
+        );
+        fee_map.remove_fee(TokenId::from(3)).unwrap();
+
+        fee_map.recompute_digest(FeeMap::DIGEST_VERSION).unwrap();
+        assert_eq!(fee_map.digest(), original_digest);
+        assert_eq!(fee_map.responder_id(&base), original_responder_id);
+    }
+
+    #[test]
+    fn assert_invariants_accepts_valid_maps() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        fee_map.assert_invariants();
+
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+        fee_map.recompute_digest(DigestVersion::V2).unwrap();
+        fee_map.assert_invariants();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "cached fee map digest is stale")]
+    fn assert_invariants_catches_stale_digest() {
+        FeeMap::with_corrupted_digest(FeeMap::default_map()).assert_invariants();
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "fee map has no MOB fee")]
+    fn assert_invariants_catches_missing_mob() {
+        FeeMap::with_corrupted_digest(BTreeMap::from_iter([(TokenId::from(2), 2000)]))
+            .assert_invariants();
+    }
+
+    #[test]
+    fn edit_guard_recomputes_digest_on_drop() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let original_digest = fee_map.digest().to_string();
+
+        {
+            let mut guard = fee_map.edit();
+            guard.set(TokenId::from(3), 3000).unwrap();
+            guard.set(Mob::ID, 200).unwrap();
+            assert_eq!(guard.remove(TokenId::from(2)), Ok(Some(2000)));
+            assert_eq!(guard.remove(TokenId::from(4)), Ok(None));
+            assert_eq!(guard.remove(Mob::ID), Err(Error::CannotRemoveMob));
+            assert_eq!(
+                guard.set(TokenId::from(5), 0),
+                Err(Error::InvalidFee(TokenId::from(5), 0))
+            );
+        }
+
+        let expected = FeeMap::try_from_iter([(Mob::ID, 200), (TokenId::from(3), 3000)]).unwrap();
+        assert_eq!(fee_map, expected);
+        assert_eq!(fee_map.digest(), expected.digest());
+        assert_ne!(fee_map.digest(), original_digest);
+        fee_map.assert_invariants();
+
+        // A guard that changes nothing leaves the digest alone.
+        fee_map.edit().set(Mob::ID, 200).unwrap();
+        assert_eq!(fee_map.digest(), expected.digest());
+    }
+
+    #[test]
+    fn view_reads_fee_map() {
+        let fee_map = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let view = fee_map.view();
+
+        assert_eq!(
+            view.get_minimum_fee_for_token(&Mob::ID),
+            Some(Fee::from(100))
+        );
+        assert_eq!(
+            view.get_minimum_fee_for_token(&TokenId::from(2)),
+            Some(Fee::from(2000))
+        );
+        assert_eq!(view.get_minimum_fee_for_token(&TokenId::from(3)), None);
+        assert_eq!(
+            view.iter().collect::<Vec<_>>(),
+            fee_map.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(view.len(), 2);
+        assert!(!view.is_empty());
+        assert_eq!(view.digest(), fee_map.digest());
+
+        assert_eq!(FeeMapView::from(&fee_map).digest(), fee_map.digest());
+    }
+
+    #[test]
+    fn entry_or_insert() {
+        let mut fee_map = FeeMap::try_from_iter([(Mob::ID, 100)]).unwrap();
+
+        // Vacant: the fee is inserted and the digest recomputed.
+        let entry = fee_map.entry(TokenId::from(2));
+        assert_eq!(entry.key(), &TokenId::from(2));
+        assert_eq!(entry.or_insert(2000), Ok(2000));
+        let expected = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        assert_eq!(fee_map, expected);
+        assert_eq!(fee_map.digest(), expected.digest());
+
+        // Occupied: the existing fee is returned and nothing changes.
+        assert_eq!(fee_map.entry(TokenId::from(2)).or_insert(3000), Ok(2000));
+        assert_eq!(fee_map.entry(Mob::ID).or_insert(0), Ok(100));
+        assert_eq!(fee_map, expected);
+
+        // Invalid fees are rejected when inserting.
+        assert_eq!(
+            fee_map.entry(TokenId::from(3)).or_insert(0),
+            Err(Error::InvalidFee(TokenId::from(3), 0))
+        );
+        assert_eq!(fee_map, expected);
+    }
+
+    #[test]
+    fn entry_only_recomputes_digest_on_insert() {
+        let mut fee_map = FeeMap::with_corrupted_digest(BTreeMap::from_iter([(Mob::ID, 100)]));
+
+        fee_map.entry(Mob::ID).or_insert(200).unwrap();
+        assert_eq!(fee_map.cached_digest, "corrupted");
+
+        fee_map.entry(TokenId::from(2)).or_insert(2000).unwrap();
+        fee_map.assert_invariants();
+    }
+
+    #[test]
+    fn union_tokens() {
+        let fee_map1 = FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        let fee_map2 = FeeMap::try_from_iter([(Mob::ID, 200), (TokenId::from(3), 3000)]).unwrap();
+
+        // Overlapping maps.
+        assert_eq!(
+            fee_map1.union_tokens(&fee_map2),
+            BTreeSet::from_iter([Mob::ID, TokenId::from(2), TokenId::from(3)])
+        );
+        assert_eq!(
+            fee_map1.union_tokens(&fee_map2),
+            fee_map2.union_tokens(&fee_map1)
+        );
+        assert_eq!(
+            fee_map1.union_tokens(&fee_map1),
+            BTreeSet::from_iter([Mob::ID, TokenId::from(2)])
+        );
+
+        // As MOB is always present, maps are never fully disjoint, but the
+        // other tokens can be.
+        assert_eq!(
+            fee_map1.union_tokens(&FeeMap::default()),
+            BTreeSet::from_iter([Mob::ID, TokenId::from(2)])
+        );
+        assert_eq!(
+            fee_map1.common_tokens(&fee_map2),
+            BTreeSet::from_iter([Mob::ID])
+        );
+    }
+
+    #[test]
+    fn fee_deltas() {
+        let old = FeeMap::try_from_iter([
+            (Mob::ID, 100),
+            (TokenId::from(2), 2000),
+            (TokenId::from(3), 3000),
+            (TokenId::from(4), 4000),
+        ])
+        .unwrap();
+        let new = FeeMap::try_from_iter([
+            (Mob::ID, 100),
+            (TokenId::from(2), 2500),
+            (TokenId::from(3), 1),
+            (TokenId::from(5), 5000),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            old.fee_deltas(&new),
+            vec![
+                // Unchanged.
+                (Mob::ID, 0),
+                // Increased.
+                (TokenId::from(2), 500),
+                // Decreased.
+                (TokenId::from(3), -2999),
+            ]
+        );
+        assert_eq!(
+            new.fee_deltas(&old),
+            vec![
+                (Mob::ID, 0),
+                (TokenId::from(2), -500),
+                (TokenId::from(3), 2999)
+            ]
+        );
+    }
+
+    #[test]
+    fn fee_map_can_be_appended_to_external_transcripts() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        for version in [DigestVersion::V1, DigestVersion::V2, DigestVersion::V3] {
+            fee_map.recompute_digest(version).unwrap();
+
+            let mut transcript = MerlinTranscript::new(fee_map.digest_version().label());
+            fee_map.append_to_transcript(b"fee_map", &mut transcript);
+            let mut standalone = [0u8; 32];
+            transcript.extract_digest(&mut standalone);
+
+            assert_eq!(standalone, fee_map.digest_bytes());
+            assert_eq!(hex::encode(standalone), fee_map.digest());
+        }
+
+        // Embedding under a different label gives a different digest.
+        let mut transcript = MerlinTranscript::new(b"block_header");
+        fee_map.append_to_transcript(b"fees", &mut transcript);
+        let mut embedded = [0u8; 32];
+        transcript.extract_digest(&mut embedded);
+        assert_ne!(embedded, fee_map.digest_bytes());
     }
 }
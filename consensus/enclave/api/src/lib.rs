@@ -9,10 +9,18 @@ extern crate alloc;
 mod error;
 mod fee_map;
 mod messages;
+#[cfg(feature = "minijson")]
+mod minijson;
 
 pub use crate::{
     error::Error,
-    fee_map::{Error as FeeMapError, FeeMap},
+    fee_map::{
+        estimate_fee, fee_entry, max_fee_for_token, split_fee_digest, DigestVersion, EpochFeeMap,
+        Error as FeeMapError, Fee, FeeEntry, FeeMap, FeeMapBuilder, FeeMapController, FeeMapDiff,
+        FeeMapDigester, FeeMapEditGuard, FeeMapSnapshot, FeeMapView, FeePolicy, FeeStatus,
+        MerlinFeeMapDigester, DEFAULT_MAXIMUM_FEE, DEFAULT_MOB_FEE_FLOOR, MAX_BASIS_POINTS,
+        PER_INPUT_SURCHARGE_BASIS_POINTS, PER_OUTPUT_SURCHARGE_BASIS_POINTS,
+    },
     messages::EnclaveCall,
 };
 
@@ -0,0 +1,233 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! A minimal JSON parser for fee maps, for use where `serde_json` is not
+//! available (e.g. in the enclave).
+//!
+//! Only objects mapping string keys to unsigned integers, or to objects with a
+//! `minimum_fee` and `basis_points`, are supported, which is exactly the format
+//! produced by [FeeMap::to_json](crate::FeeMap::to_json).
+
+use alloc::{collections::BTreeMap, format, string::String};
+use core::convert::TryFrom;
+
+/// Parse a JSON object mapping strings to fees, e.g.
+/// `{"0": 400000000, "1": {"minimum_fee": 1024, "basis_points": 25}}`, into
+/// the minimum fee and optional basis points of each key. Escape sequences in
+/// keys are not supported.
+pub fn parse_fee_object(src: &str) -> Result<BTreeMap<String, (u64, Option<u16>)>, String> {
+    let mut parser = Parser { src, pos: 0 };
+    let mut entries = BTreeMap::new();
+
+    parser.expect(b'{')?;
+    if !parser.eat(b'}') {
+        loop {
+            let key = parser.parse_string()?;
+            parser.expect(b':')?;
+            let value = parser.parse_entry()?;
+            if entries.insert(key, value).is_some() {
+                return Err(format!("duplicate key at position {}", parser.pos));
+            }
+
+            if parser.eat(b'}') {
+                break;
+            }
+            parser.expect(b',')?;
+        }
+    }
+
+    parser.skip_whitespace();
+    if parser.pos != src.len() {
+        return Err(format!("trailing characters at position {}", parser.pos));
+    }
+
+    Ok(entries)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    /// Skip whitespace, then consume `byte` if it is next.
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespace();
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.eat(byte) {
+            Ok(())
+        } else {
+            Err(format!(
+                "expected `{}` at position {}",
+                byte as char, self.pos
+            ))
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    return Err(format!(
+                        "escape sequences are not supported at position {}",
+                        self.pos
+                    ))
+                }
+                Some(_) => self.pos += 1,
+                None => return Err("unterminated string".into()),
+            }
+        }
+        let value = &self.src[start..self.pos];
+        self.pos += 1;
+        Ok(value.into())
+    }
+
+    /// Parse either a bare minimum fee, or an object with exactly a
+    /// `minimum_fee` and `basis_points`.
+    fn parse_entry(&mut self) -> Result<(u64, Option<u16>), String> {
+        if !self.eat(b'{') {
+            return Ok((self.parse_u64()?, None));
+        }
+
+        let mut minimum_fee = None;
+        let mut basis_points = None;
+        loop {
+            self.skip_whitespace();
+            let key_pos = self.pos;
+            let key = self.parse_string()?;
+            self.expect(b':')?;
+            let value = self.parse_u64()?;
+            let slot = match key.as_str() {
+                "minimum_fee" => &mut minimum_fee,
+                "basis_points" => &mut basis_points,
+                _ => return Err(format!("unexpected key `{}` at position {}", key, key_pos)),
+            };
+            if slot.replace(value).is_some() {
+                return Err(format!("duplicate key at position {}", self.pos));
+            }
+
+            if self.eat(b'}') {
+                break;
+            }
+            self.expect(b',')?;
+        }
+
+        match (minimum_fee, basis_points) {
+            (Some(minimum_fee), Some(basis_points)) => {
+                let basis_points = u16::try_from(basis_points)
+                    .map_err(|_| format!("basis points out of range at position {}", self.pos))?;
+                Ok((minimum_fee, Some(basis_points)))
+            }
+            _ => Err(format!(
+                "expected `minimum_fee` and `basis_points` at position {}",
+                self.pos
+            )),
+        }
+    }
+
+    fn parse_u64(&mut self) -> Result<u64, String> {
+        self.skip_whitespace();
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        self.src[start..self.pos]
+            .parse()
+            .map_err(|_| format!("expected an unsigned integer at position {}", start))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{FeeMap, FeeMapError, FeePolicy};
+    use alloc::string::ToString;
+    use core::iter::FromIterator;
+    use mc_transaction_core::{tokens::Mob, Token, TokenId};
+
+    #[test]
+    fn parse_fee_object_works() {
+        assert_eq!(parse_fee_object("{}"), Ok(BTreeMap::new()));
+        assert_eq!(
+            parse_fee_object(" { \"0\" : 400000000 ,\n\"1\":1024 } "),
+            Ok(BTreeMap::from_iter([
+                ("0".to_string(), (400000000, None)),
+                ("1".to_string(), (1024, None))
+            ]))
+        );
+        assert_eq!(
+            parse_fee_object("{\"1\": { \"basis_points\": 25, \"minimum_fee\": 1024 }}"),
+            Ok(BTreeMap::from_iter([("1".to_string(), (1024, Some(25)))]))
+        );
+    }
+
+    #[test]
+    fn parse_fee_object_rejects_invalid_json() {
+        for src in [
+            "",
+            "{",
+            "{\"0\"}",
+            "{\"0\": }",
+            "{\"0\": -1}",
+            "{\"0\": 1.5}",
+            "{\"0\": 18446744073709551616}",
+            "{\"0\": 1,}",
+            "{\"0\": 1} x",
+            "{\"0\": 1, \"0\": 2}",
+            "{\"\\u0030\": 1}",
+            "[1]",
+            "{\"0\": {}}",
+            "{\"0\": {\"minimum_fee\": 1}}",
+            "{\"0\": {\"minimum_fee\": 1, \"basis_points\": 65536}}",
+            "{\"0\": {\"minimum_fee\": 1, \"basis_points\": 2, \"basis_points\": 3}}",
+            "{\"0\": {\"minimum_fee\": 1, \"basis_points\": 2, \"fee\": 3}}",
+        ] {
+            assert!(parse_fee_object(src).is_err(), "{} should not parse", src);
+        }
+    }
+
+    #[test]
+    fn fee_map_round_trip() {
+        let mut fee_map =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+
+        let json = fee_map.to_json();
+        assert_eq!(FeeMap::from_minijson(&json), Ok(fee_map.clone()));
+
+        fee_map
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+        let json = fee_map.to_json();
+        assert_eq!(FeeMap::from_minijson(&json), Ok(fee_map));
+        assert_eq!(
+            FeeMap::from_minijson("{\"0\": 100, \"2\": 2000"),
+            Err(FeeMapError::Parse(
+                "expected `,` at position 20".to_string()
+            ))
+        );
+        assert_eq!(
+            FeeMap::from_minijson("{\"2\": 2000}"),
+            Err(FeeMapError::MissingFee(Mob::ID))
+        );
+    }
+}
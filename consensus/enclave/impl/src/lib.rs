@@ -209,7 +209,11 @@ impl ConsensusEnclave for SgxConsensusEnclave {
     }
 
     fn get_minimum_fee(&self, token_id: &TokenId) -> Result<Option<u64>> {
-        Ok(self.fee_map.lock()?.get_fee_for_token(token_id))
+        Ok(self
+            .fee_map
+            .lock()?
+            .get_minimum_fee_for_token(token_id)
+            .map(u64::from))
     }
 
     fn get_identity(&self) -> Result<X25519Public> {
@@ -363,10 +367,10 @@ impl ConsensusEnclave for SgxConsensusEnclave {
 
         // Validate.
         let mut csprng = McRng::default();
-        let minimum_fee = self
+        let minimum_fee = *self
             .fee_map
             .lock()?
-            .get_fee_for_token(&TokenId::MOB)
+            .get_minimum_fee_for_token(&TokenId::MOB)
             // This should actually never happen since the map enforces the existence of
             // MOB.
             .ok_or(Error::FeeMap(FeeMapError::MissingFee(TokenId::MOB)))?;
@@ -440,10 +444,10 @@ impl ConsensusEnclave for SgxConsensusEnclave {
         // ledger that were used to validate the transactions.
         let mut root_elements = Vec::new();
         let mut rng = McRng::default();
-        let minimum_fee = self
+        let minimum_fee = *self
             .fee_map
             .lock()?
-            .get_fee_for_token(&TokenId::MOB)
+            .get_minimum_fee_for_token(&TokenId::MOB)
             // This should actually never happen since the map enforces the existence of
             // MOB.
             .ok_or(Error::FeeMap(FeeMapError::MissingFee(TokenId::MOB)))?;
@@ -107,7 +107,12 @@ impl ConsensusEnclave for ConsensusServiceMockEnclave {
     }
 
     fn get_minimum_fee(&self, token_id: &TokenId) -> Result<Option<u64>> {
-        Ok(self.fee_map.lock().unwrap().get_fee_for_token(token_id))
+        Ok(self
+            .fee_map
+            .lock()
+            .unwrap()
+            .get_minimum_fee_for_token(token_id)
+            .map(u64::from))
     }
 
     fn get_identity(&self) -> Result<X25519Public> {
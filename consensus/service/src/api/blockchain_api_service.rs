@@ -2,6 +2,7 @@
 
 //! Serves blockchain-related API requests.
 
+use crate::fee_map_conversions::set_fees;
 use grpcio::{RpcContext, RpcStatus, RpcStatusCode, UnarySink};
 use mc_common::logger::{log, Logger};
 use mc_consensus_api::{
@@ -12,11 +13,10 @@ use mc_consensus_api::{
 };
 use mc_consensus_enclave::FeeMap;
 use mc_ledger_db::Ledger;
-use mc_transaction_core::{tokens::Mob, Token};
 use mc_util_grpc::{rpc_logger, send_result, Authenticator};
 use mc_util_metrics::{self, SVC_COUNTERS};
 use protobuf::RepeatedField;
-use std::{cmp, collections::HashMap, convert::From, iter::FromIterator, sync::Arc};
+use std::{cmp, convert::From, sync::Arc};
 
 #[derive(Clone)]
 pub struct BlockchainApiService<L: Ledger + Clone> {
@@ -64,16 +64,7 @@ impl<L: Ledger + Clone> BlockchainApiService<L> {
         let num_blocks = self.ledger.num_blocks()?;
         let mut resp = LastBlockInfoResponse::new();
         resp.set_index(num_blocks - 1);
-        resp.set_mob_minimum_fee(
-            self.fee_map
-                .get_fee_for_token(&Mob::ID)
-                .expect("should always have a fee for MOB"),
-        );
-        resp.set_minimum_fees(HashMap::from_iter(
-            self.fee_map
-                .iter()
-                .map(|(token_id, fee)| (**token_id, *fee)),
-        ));
+        set_fees(&mut resp, &self.fee_map);
 
         Ok(resp)
     }
@@ -179,7 +170,8 @@ mod tests {
     use grpcio::{ChannelBuilder, Environment, Error as GrpcError, Server, ServerBuilder};
     use mc_common::{logger::test_with_logger, time::SystemTimeProvider};
     use mc_consensus_api::consensus_common_grpc::{self, BlockchainApiClient};
-    use mc_transaction_core::TokenId;
+    use mc_consensus_enclave::FeePolicy;
+    use mc_transaction_core::{tokens::Mob, Token, TokenId};
     use mc_transaction_core_test_utils::{create_ledger, initialize_ledger, AccountKey};
     use mc_util_grpc::{AnonymousAuthenticator, TokenAuthenticator};
     use rand::{rngs::StdRng, SeedableRng};
@@ -216,8 +208,11 @@ mod tests {
     #[test_with_logger]
     // `get_last_block_info` should returns the last block.
     fn test_get_last_block_info(logger: Logger) {
-        let fee_map =
+        let mut fee_map =
             FeeMap::try_from_iter([(Mob::ID, 12345), (TokenId::from(60), 10203040)]).unwrap();
+        fee_map
+            .set_fee_policy(TokenId::from(60), FeePolicy::BasisPoints(25))
+            .unwrap();
 
         let mut ledger_db = create_ledger();
         let authenticator = Arc::new(AnonymousAuthenticator::default());
@@ -229,6 +224,7 @@ mod tests {
         expected_response.set_index(block_entities.last().unwrap().index);
         expected_response.set_mob_minimum_fee(12345);
         expected_response.set_minimum_fees(HashMap::from_iter(vec![(0, 12345), (60, 10203040)]));
+        expected_response.set_fee_basis_points(HashMap::from_iter(vec![(60, 25)]));
         assert_eq!(
             block_entities.last().unwrap().index,
             ledger_db.num_blocks().unwrap() - 1
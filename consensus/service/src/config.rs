@@ -14,7 +14,10 @@ use mc_util_uri::{
     AdminUri, ConnectionUri, ConsensusClientUri as ClientUri, ConsensusPeerUri as PeerUri,
 };
 use serde::{Deserialize, Serialize};
-use std::{fmt::Debug, fs, path::PathBuf, str::FromStr, string::String, sync::Arc, time::Duration};
+use std::{
+    collections::BTreeMap, convert::TryFrom, fmt::Debug, fs, iter::FromIterator, path::PathBuf,
+    str::FromStr, string::String, sync::Arc, time::Duration,
+};
 use structopt::StructOpt;
 
 #[derive(Clone, Debug, StructOpt)]
@@ -219,17 +222,24 @@ impl Config {
             return Ok(FeeMap::default());
         }
 
-        let fee_map = FeeMap::try_from_iter(
+        let minimum_fees = BTreeMap::from_iter(
             self.minimum_fee
                 .iter()
                 .cloned()
                 .map(|pair| (pair.0, pair.1)),
-        )
+        );
+
+        // Allowing any fee also lifts the default fee ceiling.
+        let fee_map = if self.allow_any_fee {
+            FeeMap::with_max_fee(minimum_fees, u64::MAX)
+        } else {
+            FeeMap::try_from(minimum_fees)
+        }
         .map_err(|err| ConsensusServiceError::FeesMisconfigured(err.to_string()))?;
 
         // Must have a fee for MOB (this is enforced by is_valid_map above).
-        let mob_fee = fee_map
-            .get_fee_for_token(&TokenId::MOB)
+        let mob_fee = *fee_map
+            .get_minimum_fee_for_token(&TokenId::MOB)
             .expect("MOB fee must be specified");
 
         if !self.allow_any_fee && !(10_000..1_000_000_000_000u64).contains(&mob_fee) {
@@ -322,11 +332,9 @@ impl FromStr for TokenIdFeePair {
             ));
         }
 
-        let token_id = TokenId::from(
-            elements[0]
-                .parse::<u32>()
-                .map_err(|_| format!("{} is not a valid integer", elements[0]))?,
-        );
+        let token_id = elements[0]
+            .parse::<TokenId>()
+            .map_err(|_| format!("{} is not a valid token id", elements[0]))?;
         let minimum_fee = elements[1]
             .parse::<u64>()
             .map_err(|_| format!("{} is not a valid integer", elements[0]))?;
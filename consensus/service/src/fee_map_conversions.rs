@@ -0,0 +1,140 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! Conversions between the fees in a `LastBlockInfoResponse` and a [FeeMap].
+//!
+//! These live here rather than in `mc-consensus-api`, so that the API crate
+//! does not depend on the enclave API.
+
+use mc_consensus_api::consensus_common::LastBlockInfoResponse;
+use mc_consensus_enclave::{FeeMap, FeeMapError, FeePolicy};
+use mc_transaction_core::{tokens::Mob, Token, TokenId};
+use std::{collections::HashMap, convert::TryFrom, iter::FromIterator};
+
+/// Set the fees of a `LastBlockInfoResponse` from a fee map: the minimum fee
+/// of each token, the deprecated MOB minimum fee, and the basis points of each
+/// proportional fee.
+pub fn set_fees(response: &mut LastBlockInfoResponse, fee_map: &FeeMap) {
+    response.set_mob_minimum_fee(
+        *fee_map
+            .get_minimum_fee_for_token(&Mob::ID)
+            .expect("should always have a fee for MOB"),
+    );
+    response.set_minimum_fees(HashMap::from_iter(
+        fee_map.iter().map(|(token_id, fee)| (**token_id, *fee)),
+    ));
+    response.set_fee_basis_points(HashMap::from_iter(fee_map.token_ids().filter_map(
+        |token_id| match fee_map.fee_policy(&token_id) {
+            Some(FeePolicy::BasisPoints(basis_points)) => Some((*token_id, basis_points as u32)),
+            _ => None,
+        },
+    )));
+}
+
+/// Recover the fee map from the fees of a `LastBlockInfoResponse`.
+///
+/// The received fees are validated and the digest recomputed.
+pub fn get_fee_map(response: &LastBlockInfoResponse) -> Result<FeeMap, FeeMapError> {
+    let mut fee_map = FeeMap::try_from_iter(
+        response
+            .get_minimum_fees()
+            .iter()
+            .map(|(token_id, fee)| (TokenId::from(*token_id), *fee)),
+    )?;
+
+    for (token_id, basis_points) in response.get_fee_basis_points() {
+        let token_id = TokenId::from(*token_id);
+        // Values which fit in a u16 are range checked by set_fee_policy.
+        let basis_points = u16::try_from(*basis_points).map_err(|_| {
+            FeeMapError::Parse(format!(
+                "token {} has basis points {} which are out of range",
+                token_id, basis_points
+            ))
+        })?;
+        fee_map.set_fee_policy(token_id, FeePolicy::BasisPoints(basis_points))?;
+    }
+
+    Ok(fee_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    // FeeMap --> LastBlockInfoResponse --> FeeMap
+    fn test_fee_map_round_trip() {
+        let mut with_policy =
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap();
+        with_policy
+            .set_fee_policy(TokenId::from(2), FeePolicy::BasisPoints(25))
+            .unwrap();
+
+        let fee_maps = [
+            FeeMap::default(),
+            FeeMap::try_from_iter([(Mob::ID, 100), (TokenId::from(2), 2000)]).unwrap(),
+            with_policy,
+        ];
+
+        for source in fee_maps.iter() {
+            let mut response = LastBlockInfoResponse::new();
+            set_fees(&mut response, source);
+            assert_eq!(response.get_minimum_fees().len(), source.len());
+
+            let recovered = get_fee_map(&response).unwrap();
+            assert_eq!(&recovered, source);
+            assert_eq!(recovered.digest(), source.digest());
+        }
+    }
+
+    #[test]
+    // Fees without a MOB fee should be rejected.
+    fn test_fee_map_missing_mob_is_rejected() {
+        let mut response = LastBlockInfoResponse::new();
+        response.set_minimum_fees(HashMap::from_iter(vec![(2, 2000)]));
+
+        assert_eq!(
+            get_fee_map(&response),
+            Err(FeeMapError::MissingFee(Mob::ID))
+        );
+    }
+
+    #[test]
+    // Fees with a zero fee should be rejected.
+    fn test_fee_map_zero_fee_is_rejected() {
+        let mut response = LastBlockInfoResponse::new();
+        response.set_minimum_fees(HashMap::from_iter(vec![(0, 100), (2, 0)]));
+
+        assert_eq!(
+            get_fee_map(&response),
+            Err(FeeMapError::InvalidFee(TokenId::from(2), 0))
+        );
+    }
+
+    #[test]
+    // Basis points out of range, or for a token without a minimum fee, should
+    // be rejected.
+    fn test_fee_map_invalid_basis_points_are_rejected() {
+        let mut response = LastBlockInfoResponse::new();
+        response.set_minimum_fees(HashMap::from_iter(vec![(0, 100), (2, 2000)]));
+
+        response.set_fee_basis_points(HashMap::from_iter(vec![(2, 70_000)]));
+        assert_eq!(
+            get_fee_map(&response),
+            Err(FeeMapError::Parse(
+                "token 2 has basis points 70000 which are out of range".to_string()
+            ))
+        );
+
+        response.set_fee_basis_points(HashMap::from_iter(vec![(2, 20_000)]));
+        assert_eq!(
+            get_fee_map(&response),
+            Err(FeeMapError::InvalidBasisPoints(TokenId::from(2), 20_000))
+        );
+
+        response.set_fee_basis_points(HashMap::from_iter(vec![(3, 25)]));
+        assert_eq!(
+            get_fee_map(&response),
+            Err(FeeMapError::MissingFee(TokenId::from(3)))
+        );
+    }
+}
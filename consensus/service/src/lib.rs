@@ -9,6 +9,7 @@ extern crate test;
 
 pub mod config;
 pub mod consensus_service;
+pub mod fee_map_conversions;
 pub mod tx_manager;
 pub mod validators; // Public so that it can be benchmarked by the `benchmarks` crate.
 
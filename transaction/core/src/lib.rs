@@ -20,6 +20,7 @@ mod blockchain;
 mod domain_separators;
 mod memo;
 mod token;
+mod token_registry;
 mod tx_error;
 
 pub mod constants;
@@ -38,7 +39,8 @@ pub mod proptest_fixtures;
 pub use amount::{get_value_mask, Amount, AmountError, Commitment, CompressedCommitment};
 pub use blockchain::*;
 pub use memo::{EncryptedMemo, MemoError, MemoPayload};
-pub use token::{tokens, Token, TokenId};
+pub use token::{format_amount, tokens, Token, TokenId};
+pub use token_registry::{TokenInfo, TokenRegistry, TokenRegistryError};
 pub use tx::MemoContext;
 pub use tx_error::{NewMemoError, NewTxError};
 
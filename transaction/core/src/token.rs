@@ -1,6 +1,14 @@
 // Copyright (c) 2018-2021 The MobileCoin Foundation
 
-use core::{fmt, hash::Hash, ops::Deref};
+use alloc::{format, string::String};
+use core::{
+    convert::TryFrom,
+    fmt,
+    hash::Hash,
+    num::{ParseIntError, TryFromIntError},
+    ops::Deref,
+    str::FromStr,
+};
 use mc_crypto_digestible::Digestible;
 use serde::{Deserialize, Serialize};
 
@@ -8,6 +16,7 @@ use serde::{Deserialize, Serialize};
 #[derive(
     Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord, Serialize, Deserialize, Digestible, Hash,
 )]
+#[serde(transparent)]
 pub struct TokenId(u32);
 
 impl From<u32> for TokenId {
@@ -16,14 +25,101 @@ impl From<u32> for TokenId {
     }
 }
 
+impl TryFrom<u64> for TokenId {
+    type Error = TryFromIntError;
+
+    fn try_from(src: u64) -> Result<Self, Self::Error> {
+        u32::try_from(src).map(Self)
+    }
+}
+
+impl FromStr for TokenId {
+    type Err = ParseIntError;
+
+    /// Parse a token id from the symbol of a built-in token, e.g. `MOB`, a
+    /// decimal string, or a hex string prefixed with `0x`. This accepts
+    /// everything the `Display` impl produces.
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        let known_token = tokens::known_tokens().find(|(_token_id, symbol)| *symbol == src);
+        if let Some((token_id, _symbol)) = known_token {
+            return Ok(token_id);
+        }
+
+        match src.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => src.parse::<u32>(),
+        }
+        .map(Self)
+    }
+}
+
 impl fmt::Display for TokenId {
+    /// Displays the symbol of a built-in token, e.g. `MOB`, and the numeric
+    /// token id otherwise. The output can be parsed back with `FromStr`. Use
+    /// the `Debug` form, or dereference the token id, where the number is
+    /// always wanted.
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match tokens::known_tokens().find(|(token_id, _symbol)| token_id == self) {
+            Some((_token_id, symbol)) => write!(f, "{}", symbol),
+            None => write!(f, "{}", self.0),
+        }
     }
 }
 
 impl TokenId {
+    /// The token id of MOB.
     pub const MOB: Self = Self(0);
+
+    /// The token id of eUSD.
+    pub const EUSD: Self = Self(1);
+
+    /// The largest token id reserved for protocol-assigned assets. Token ids
+    /// up to and including this one (i.e. below `0x0001_0000`) must not be
+    /// used by other issuers.
+    pub const RESERVED_MAX: Self = Self(0xFFFF);
+
+    /// Returns true if this is the MOB token id.
+    pub const fn is_mob(&self) -> bool {
+        self.0 == Self::MOB.0
+    }
+
+    /// Returns true if this token id is reserved for protocol-assigned
+    /// assets. See [TokenId::RESERVED_MAX].
+    pub const fn is_reserved(&self) -> bool {
+        self.0 <= Self::RESERVED_MAX.0
+    }
+
+    /// A hash of this token id which is the same on every platform and in every
+    /// run, unlike the `Hash` impl with std's `RandomState`. This is the 64-bit
+    /// FNV-1a hash of the little-endian bytes of the id.
+    pub fn stable_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+        const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+        self.0
+            .to_le_bytes()
+            .iter()
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    /// The big-endian byte encoding of this token id, for wire formats which
+    /// encode token ids as fixed bytes.
+    pub const fn to_be_bytes(&self) -> [u8; 4] {
+        self.0.to_be_bytes()
+    }
+
+    /// Decode a token id from the encoding produced by [TokenId::to_be_bytes].
+    pub const fn from_be_bytes(bytes: [u8; 4]) -> Self {
+        Self(u32::from_be_bytes(bytes))
+    }
+
+    /// Returns the token id after this one, or None if this is the largest
+    /// token id.
+    pub fn checked_next(&self) -> Option<TokenId> {
+        self.0.checked_add(1).map(Self)
+    }
 }
 
 impl Deref for TokenId {
@@ -41,11 +137,106 @@ pub trait Token {
 
     /// Mininum fee for this token.
     const MINIMUM_FEE: u64;
+
+    /// Human-readable symbol for this token.
+    const SYMBOL: &'static str;
+
+    /// Number of decimal places in one whole token, i.e. one whole token is
+    /// `10^DECIMALS` of the smallest unit amounts are denominated in.
+    const DECIMALS: u8;
+
+    /// Maximum supply of this token, or None if the supply is uncapped.
+    ///
+    /// This is denominated in whole tokens rather than the smallest unit,
+    /// since e.g. the total supply of MOB in picoMOB does not fit in a u64.
+    const MAX_SUPPLY: Option<u64> = None;
+
+    /// Returns true if `amount` is at least the minimum fee for this token.
+    fn is_fee_sufficient(amount: u64) -> bool {
+        amount >= Self::MINIMUM_FEE
+    }
+}
+
+/// Format an amount of a token, denominated in its smallest unit, in whole
+/// tokens followed by the token's symbol, e.g. `"0.0004 MOB"` for 400 microMOB.
+/// Trailing zeros in the fractional part are omitted.
+pub fn format_amount<T: Token>(amount: u64) -> String {
+    let decimals = T::DECIMALS as usize;
+    // If one whole token does not fit in a u64, no amount reaches it.
+    let (whole, fractional) = match 10u64.checked_pow(T::DECIMALS.into()) {
+        Some(scale) => (amount / scale, amount % scale),
+        None => (0, amount),
+    };
+
+    if fractional == 0 {
+        format!("{} {}", whole, T::SYMBOL)
+    } else {
+        let fractional = format!("{:0width$}", fractional, width = decimals);
+        format!(
+            "{}.{} {}",
+            whole,
+            fractional.trim_end_matches('0'),
+            T::SYMBOL
+        )
+    }
+}
+
+/// Fails to compile if the given [Token] type has a `MINIMUM_FEE` of zero,
+/// since a fee map can never contain a zero fee.
+///
+/// Usage:
+/// ```
+/// use mc_transaction_core::{assert_token_valid, tokens::Mob};
+///
+/// assert_token_valid!(Mob);
+/// ```
+///
+/// A token with a zero minimum fee is rejected with a hard error (E0080):
+/// ```compile_fail,E0080
+/// use mc_transaction_core::{assert_token_valid, Token, TokenId};
+///
+/// struct Free;
+/// impl Token for Free {
+///     const ID: TokenId = TokenId::EUSD;
+///     const MINIMUM_FEE: u64 = 0;
+///     const SYMBOL: &'static str = "FREE";
+///     const DECIMALS: u8 = 6;
+/// }
+///
+/// assert_token_valid!(Free);
+/// ```
+#[macro_export]
+macro_rules! assert_token_valid {
+    ($token:ty) => {
+        // The array length underflows for a zero fee, which is always a
+        // compile-time error (E0080) rather than a lint.
+        const _: [(); 0 - ((<$token as $crate::Token>::MINIMUM_FEE == 0) as usize)] = [];
+    };
+}
+
+/// Expands to an array of `(TokenId, u64)` pairs holding the `ID` and
+/// `MINIMUM_FEE` of each of the given [Token] types. The result can be used in
+/// a const context.
+///
+/// Usage:
+/// ```
+/// use mc_transaction_core::{const_fee_entries, tokens::Mob, TokenId};
+///
+/// const FEES: [(TokenId, u64); 1] = const_fee_entries!(Mob);
+/// ```
+#[macro_export]
+macro_rules! const_fee_entries {
+    ($($token:ty),* $(,)?) => {
+        [$((
+            <$token as $crate::Token>::ID,
+            <$token as $crate::Token>::MINIMUM_FEE,
+        )),*]
+    };
 }
 
 pub mod tokens {
     use super::*;
-    use crate::constants::MICROMOB_TO_PICOMOB;
+    use crate::constants::{MICROMOB_TO_PICOMOB, TOTAL_MOB};
 
     /// The MOB token.
     pub struct Mob;
@@ -55,5 +246,267 @@ pub mod tokens {
 
         /// Minimum fee, deominated in picoMOB.
         const MINIMUM_FEE: u64 = 400 * MICROMOB_TO_PICOMOB;
+
+        /// Symbol.
+        const SYMBOL: &'static str = "MOB";
+
+        /// Decimals. Amounts are denominated in picoMOB, so one MOB is 10^12
+        /// picoMOB, and one microMOB is `MICROMOB_TO_PICOMOB` (10^6) picoMOB.
+        const DECIMALS: u8 = 12;
+
+        /// Maximum supply, denominated in MOB.
+        const MAX_SUPPLY: Option<u64> = Some(TOTAL_MOB);
+    }
+    assert_token_valid!(Mob);
+
+    /// The id and symbol of each built-in token, in order of token id,
+    /// starting with MOB. New built-in tokens must be added here.
+    pub fn known_tokens() -> impl Iterator<Item = (TokenId, &'static str)> {
+        IntoIterator::into_iter([(Mob::ID, Mob::SYMBOL)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn token_id_from_str() {
+        assert_eq!(TokenId::from_str("0"), Ok(TokenId::MOB));
+        assert_eq!(TokenId::from_str("42"), Ok(TokenId::from(42)));
+        assert_eq!(TokenId::from_str("0x2a"), Ok(TokenId::from(42)));
+        assert_eq!(TokenId::from_str("4294967295"), Ok(TokenId::from(u32::MAX)));
+
+        // Built-in token symbols
+        assert_eq!(TokenId::from_str("MOB"), Ok(TokenId::MOB));
+
+        // Overflow
+        assert!(TokenId::from_str("4294967296").is_err());
+        assert!(TokenId::from_str("0x100000000").is_err());
+
+        // Non-numeric
+        assert!(TokenId::from_str("").is_err());
+        assert!(TokenId::from_str("mob").is_err());
+        assert!(TokenId::from_str("MOB ").is_err());
+        assert!(TokenId::from_str("-1").is_err());
+        assert!(TokenId::from_str("0x").is_err());
+    }
+
+    #[test]
+    fn token_id_try_from_u64() {
+        assert_eq!(TokenId::try_from(0u64), Ok(TokenId::MOB));
+        assert_eq!(TokenId::try_from(42u64), Ok(TokenId::from(42)));
+        assert_eq!(
+            TokenId::try_from(u32::MAX as u64),
+            Ok(TokenId::from(u32::MAX))
+        );
+
+        // Values that do not fit in a u32 must not be truncated.
+        assert!(TokenId::try_from(u32::MAX as u64 + 1).is_err());
+        assert!(TokenId::try_from(u64::MAX).is_err());
+    }
+
+    #[test]
+    fn token_id_is_mob() {
+        assert!(TokenId::MOB.is_mob());
+        assert!(tokens::Mob::ID.is_mob());
+        assert!(!TokenId::from(1).is_mob());
+    }
+
+    #[test]
+    fn mob_symbol() {
+        assert_eq!(tokens::Mob::SYMBOL, "MOB");
+    }
+
+    #[test]
+    fn mob_decimals() {
+        use crate::constants::{MICROMOB_TO_PICOMOB, MILLIMOB_TO_PICOMOB};
+
+        assert_eq!(tokens::Mob::DECIMALS, 12);
+        assert_eq!(
+            10u64.pow(tokens::Mob::DECIMALS as u32 - 6),
+            MICROMOB_TO_PICOMOB
+        );
+        assert_eq!(
+            10u64.pow(tokens::Mob::DECIMALS as u32 - 3),
+            MILLIMOB_TO_PICOMOB
+        );
+    }
+
+    #[test]
+    fn well_known_token_ids() {
+        assert_eq!(*TokenId::MOB, 0);
+        assert_eq!(*TokenId::EUSD, 1);
+    }
+
+    #[test]
+    fn token_id_display() {
+        // Built-in tokens display their symbol, others their numeric id.
+        assert_eq!(format!("{}", TokenId::MOB), "MOB");
+        assert_eq!(format!("{}", tokens::Mob::ID), tokens::Mob::SYMBOL);
+        assert_eq!(format!("{}", TokenId::from(42)), "42");
+        assert_eq!(format!("{}", TokenId::from(u32::MAX)), "4294967295");
+
+        // Debug always shows the raw struct.
+        assert_eq!(format!("{:?}", TokenId::MOB), "TokenId(0)");
+        assert_eq!(format!("{:?}", TokenId::from(42)), "TokenId(42)");
+    }
+
+    #[test]
+    fn token_id_display_round_trips() {
+        let token_ids = [
+            TokenId::MOB,
+            TokenId::EUSD,
+            TokenId::from(42),
+            TokenId::RESERVED_MAX,
+            TokenId::from(u32::MAX),
+        ];
+        for token_id in token_ids {
+            assert_eq!(TokenId::from_str(&format!("{}", token_id)), Ok(token_id));
+        }
+    }
+
+    /// Token ids serialize as a bare integer.
+    #[test]
+    fn token_id_serde_is_transparent() {
+        assert_eq!(serde_json::to_string(&TokenId::from(5)).unwrap(), "5");
+        assert_eq!(
+            serde_json::from_str::<TokenId>("5").unwrap(),
+            TokenId::from(5)
+        );
+        assert!(serde_json::from_str::<TokenId>("-5").is_err());
+    }
+
+    #[test]
+    fn mob_is_fee_sufficient() {
+        use tokens::Mob;
+
+        assert!(Mob::is_fee_sufficient(Mob::MINIMUM_FEE));
+        assert!(Mob::is_fee_sufficient(Mob::MINIMUM_FEE + 1));
+        assert!(!Mob::is_fee_sufficient(Mob::MINIMUM_FEE - 1));
+        assert!(!Mob::is_fee_sufficient(0));
+    }
+
+    #[test]
+    fn const_fee_entries() {
+        use tokens::Mob;
+
+        struct TestToken;
+        impl Token for TestToken {
+            const ID: TokenId = TokenId(2);
+            const MINIMUM_FEE: u64 = 1024;
+            const SYMBOL: &'static str = "TEST";
+            const DECIMALS: u8 = 6;
+        }
+
+        const ENTRIES: [(TokenId, u64); 2] = const_fee_entries!(Mob, TestToken);
+        assert_eq!(
+            ENTRIES,
+            [(TokenId::MOB, Mob::MINIMUM_FEE), (TokenId::from(2), 1024)]
+        );
+    }
+
+    #[test]
+    fn assert_token_valid_accepts_nonzero_fees() {
+        struct TestToken;
+        impl Token for TestToken {
+            const ID: TokenId = TokenId(2);
+            const MINIMUM_FEE: u64 = 1;
+            const SYMBOL: &'static str = "TEST";
+            const DECIMALS: u8 = 6;
+        }
+
+        // These expand to const items, so compiling this test is the check.
+        assert_token_valid!(TestToken);
+        assert_token_valid!(tokens::Mob);
+        assert!(TestToken::is_fee_sufficient(TestToken::MINIMUM_FEE));
+    }
+
+    #[test]
+    fn token_id_checked_next() {
+        assert_eq!(TokenId::MOB.checked_next(), Some(TokenId::from(1)));
+        assert_eq!(TokenId::from(41).checked_next(), Some(TokenId::from(42)));
+        assert_eq!(TokenId::from(u32::MAX).checked_next(), None);
+    }
+
+    #[test]
+    fn token_id_be_bytes() {
+        assert_eq!(TokenId::from(0x0102_0304).to_be_bytes(), [1, 2, 3, 4]);
+        assert_eq!(TokenId::MOB.to_be_bytes(), [0; 4]);
+        assert_eq!(
+            TokenId::from_be_bytes([0, 0, 0x01, 0x00]),
+            TokenId::from(256)
+        );
+
+        let token_ids = [
+            TokenId::MOB,
+            TokenId::EUSD,
+            TokenId::from(42),
+            TokenId::from(u32::MAX),
+        ];
+        for token_id in token_ids {
+            assert_eq!(TokenId::from_be_bytes(token_id.to_be_bytes()), token_id);
+        }
+    }
+
+    #[test]
+    fn mob_max_supply() {
+        use tokens::Mob;
+
+        assert_eq!(Mob::MAX_SUPPLY, Some(250_000_000));
+
+        // The supply in picoMOB is too large to be represented as an amount,
+        // which is why the cap is denominated in whole tokens.
+        let max_supply_picomob =
+            Mob::MAX_SUPPLY.unwrap() as u128 * 10u128.pow(Mob::DECIMALS.into());
+        assert!(max_supply_picomob > u64::MAX as u128);
+    }
+
+    #[test]
+    fn token_id_is_reserved() {
+        assert!(TokenId::MOB.is_reserved());
+        assert!(TokenId::EUSD.is_reserved());
+        assert!(TokenId::RESERVED_MAX.is_reserved());
+        assert!(TokenId::from(0xFFFF).is_reserved());
+
+        assert!(!TokenId::from(0x0001_0000).is_reserved());
+        assert_eq!(
+            TokenId::RESERVED_MAX.checked_next(),
+            Some(TokenId::from(0x0001_0000))
+        );
+        assert!(!TokenId::from(u32::MAX).is_reserved());
+    }
+
+    #[test]
+    fn token_id_stable_hash() {
+        assert_eq!(TokenId::MOB.stable_hash(), 0x4d25_767f_9dce_13f5);
+        assert_eq!(TokenId::EUSD.stable_hash(), 0xad2a_ca77_4798_5764);
+        assert_eq!(TokenId::from(42).stable_hash(), 0x8d9a_adc8_352f_df7f);
+    }
+
+    #[test]
+    fn known_tokens() {
+        let known = tokens::known_tokens().collect::<alloc::vec::Vec<_>>();
+        assert_eq!(known.first(), Some(&(TokenId::MOB, "MOB")));
+
+        // Built-in tokens are reserved, and listed in order of token id.
+        assert!(known
+            .iter()
+            .all(|(token_id, _symbol)| token_id.is_reserved()));
+        assert!(known.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn format_amount_works() {
+        use tokens::Mob;
+
+        assert_eq!(format_amount::<Mob>(0), "0 MOB");
+        assert_eq!(format_amount::<Mob>(1_000_000_000_000), "1 MOB");
+        assert_eq!(format_amount::<Mob>(250_000_000_000_000), "250 MOB");
+
+        assert_eq!(format_amount::<Mob>(Mob::MINIMUM_FEE), "0.0004 MOB");
+        assert_eq!(format_amount::<Mob>(1), "0.000000000001 MOB");
+        assert_eq!(format_amount::<Mob>(1_500_000_000_000), "1.5 MOB");
+        assert_eq!(format_amount::<Mob>(u64::MAX), "18446744.073709551615 MOB");
     }
 }
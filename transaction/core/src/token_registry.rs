@@ -0,0 +1,158 @@
+// Copyright (c) 2018-2022 The MobileCoin Foundation
+
+//! A runtime registry of token metadata, for tokens which are not known at
+//! compile time.
+
+use crate::{tokens::Mob, Token, TokenId};
+use alloc::{collections::BTreeMap, string::String};
+use displaydoc::Display;
+
+/// Metadata describing a token.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenInfo {
+    /// Human-readable symbol for this token.
+    pub symbol: String,
+
+    /// Number of decimal places in one whole token.
+    pub decimals: u8,
+
+    /// Minimum fee for this token.
+    pub minimum_fee: u64,
+}
+
+impl TokenInfo {
+    /// Get the metadata for a token known at compile time.
+    pub fn from_token<T: Token>() -> Self {
+        Self {
+            symbol: T::SYMBOL.into(),
+            decimals: T::DECIMALS,
+            minimum_fee: T::MINIMUM_FEE,
+        }
+    }
+}
+
+/// A map of token id to token metadata.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TokenRegistry {
+    tokens: BTreeMap<TokenId, TokenInfo>,
+}
+
+impl TokenRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a registry containing the built-in tokens.
+    pub fn with_builtin_tokens() -> Self {
+        let mut registry = Self::new();
+        registry
+            .register_token::<Mob>()
+            .expect("built-in tokens have distinct ids");
+        registry
+    }
+
+    /// Register a token. Token ids may only be registered once.
+    pub fn register(
+        &mut self,
+        token_id: TokenId,
+        info: TokenInfo,
+    ) -> Result<(), TokenRegistryError> {
+        if self.tokens.contains_key(&token_id) {
+            return Err(TokenRegistryError::DuplicateTokenId(token_id));
+        }
+
+        self.tokens.insert(token_id, info);
+        Ok(())
+    }
+
+    /// Register a token known at compile time.
+    pub fn register_token<T: Token>(&mut self) -> Result<(), TokenRegistryError> {
+        self.register(T::ID, TokenInfo::from_token::<T>())
+    }
+
+    /// Get the metadata for a token id, or None if it is not registered.
+    pub fn get(&self, token_id: &TokenId) -> Option<&TokenInfo> {
+        self.tokens.get(token_id)
+    }
+
+    /// Iterate over all registered tokens, in order of token id.
+    pub fn iter(&self) -> impl Iterator<Item = (&TokenId, &TokenInfo)> {
+        self.tokens.iter()
+    }
+}
+
+/// An error that occurs when registering a token
+#[derive(Clone, Debug, Display, Eq, PartialEq)]
+pub enum TokenRegistryError {
+    /// Token id {0} is already registered
+    DuplicateTokenId(TokenId),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn builtin_tokens_are_registered() {
+        let registry = TokenRegistry::with_builtin_tokens();
+
+        let mob = registry.get(&TokenId::MOB).unwrap();
+        assert_eq!(mob.symbol, "MOB");
+        assert_eq!(mob.decimals, Mob::DECIMALS);
+        assert_eq!(mob.minimum_fee, Mob::MINIMUM_FEE);
+
+        assert_eq!(registry.iter().count(), 1);
+    }
+
+    #[test]
+    fn register_and_get() {
+        let mut registry = TokenRegistry::with_builtin_tokens();
+        let info = TokenInfo {
+            symbol: "TEST".into(),
+            decimals: 6,
+            minimum_fee: 1024,
+        };
+
+        assert_eq!(registry.get(&TokenId::from(2)), None);
+        registry.register(TokenId::from(2), info.clone()).unwrap();
+        assert_eq!(registry.get(&TokenId::from(2)), Some(&info));
+
+        assert_eq!(
+            registry
+                .iter()
+                .map(|(token_id, _)| *token_id)
+                .collect::<Vec<_>>(),
+            vec![TokenId::MOB, TokenId::from(2)]
+        );
+    }
+
+    #[test]
+    fn duplicate_token_ids_are_rejected() {
+        let mut registry = TokenRegistry::with_builtin_tokens();
+        let info = TokenInfo {
+            symbol: "TEST".into(),
+            decimals: 6,
+            minimum_fee: 1024,
+        };
+
+        assert_eq!(
+            registry.register(TokenId::MOB, info.clone()),
+            Err(TokenRegistryError::DuplicateTokenId(TokenId::MOB))
+        );
+        assert_eq!(
+            registry.register_token::<Mob>(),
+            Err(TokenRegistryError::DuplicateTokenId(TokenId::MOB))
+        );
+
+        registry.register(TokenId::from(2), info.clone()).unwrap();
+        assert_eq!(
+            registry.register(TokenId::from(2), info),
+            Err(TokenRegistryError::DuplicateTokenId(TokenId::from(2)))
+        );
+
+        // The original registration is preserved.
+        assert_eq!(registry.get(&TokenId::MOB).unwrap().symbol, "MOB");
+    }
+}